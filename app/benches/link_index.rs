@@ -0,0 +1,107 @@
+//! Compares the in-memory link index with the SQLite path it replaces for
+//! redirects (chain resolution) and dashboard searches. Both searches find
+//! their candidates in `links_fts`; the index reads and ranks the links from
+//! memory, SQLite joins them from the tables.
+
+use app::{
+    analytics::HitRecorder,
+    chain,
+    index::LinkIndex,
+    models::{NewLink, SearchParams, SortOrder},
+    queries, setup_db,
+    url_policy::UrlPolicy,
+};
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+const LINKS: usize = 2_000;
+const WORDS: &[&str] = &[
+    "kubernetes",
+    "grafana",
+    "jira",
+    "wiki",
+    "docs",
+    "deploy",
+    "status",
+    "git",
+    "ci",
+    "metrics",
+];
+
+fn search(q: &str) -> SearchParams {
+    SearchParams {
+        q: Some(q.to_string()),
+        page: 1,
+        limit: 10,
+        sort: SortOrder::Recent,
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("bench.db");
+    let (pool, index) = rt.block_on(async {
+        let pool = setup_db(db.to_str().unwrap()).await.unwrap();
+        for i in 0..LINKS {
+            let word = WORDS[i % WORDS.len()];
+            let link = NewLink {
+                short_link: format!("{}-{}", word, i),
+                url: format!("https://{}.example/{}", word, i),
+                description: format!("The {} page number {}", word, i),
+                tags: vec![word.to_string()],
+            };
+            queries::insert_link(&pool, &link, None).await.unwrap();
+        }
+        let index = LinkIndex::new(pool.clone(), HitRecorder::spawn(pool.clone()));
+        index.reload().await.unwrap();
+        (pool, index)
+    });
+    let urls = UrlPolicy::default();
+
+    let mut group = c.benchmark_group("resolve");
+    group.bench_function("sqlite", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                let mut conn = pool.acquire().await.unwrap();
+                chain::follow(&mut *conn, &urls, black_box("wiki-1003/page"), 5, None)
+                    .await
+                    .unwrap()
+            })
+        })
+    });
+    group.bench_function("index", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                chain::follow(
+                    &mut index.clone(),
+                    &urls,
+                    black_box("wiki-1003/page"),
+                    5,
+                    None,
+                )
+                .await
+                .unwrap()
+            })
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("search");
+    for q in ["kbernetes-12", "wik"] {
+        let params = search(q);
+        group.bench_function(format!("sqlite/{}", q), |b| {
+            b.iter(|| {
+                rt.block_on(queries::search_links(&pool, black_box(&params)))
+                    .unwrap()
+            })
+        });
+        group.bench_function(format!("index/{}", q), |b| {
+            b.iter(|| rt.block_on(index.search(black_box(&params))).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
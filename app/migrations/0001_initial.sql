@@ -0,0 +1,18 @@
+-- Schema created by the original ad-hoc setup; IF NOT EXISTS lets databases
+-- from before schema versioning adopt it unchanged.
+CREATE TABLE IF NOT EXISTS links (
+    short_link TEXT PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE VIRTUAL TABLE IF NOT EXISTS links_fts USING fts5(short_link, url, content='links', content_rowid='rowid', tokenize='trigram');
+CREATE TRIGGER IF NOT EXISTS links_ai AFTER INSERT ON links BEGIN
+    INSERT INTO links_fts(rowid, short_link, url) VALUES (new.rowid, new.short_link, new.url);
+END;
+CREATE TRIGGER IF NOT EXISTS links_ad AFTER DELETE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_link, url) VALUES('delete', old.rowid, old.short_link, old.url);
+END;
+CREATE TRIGGER IF NOT EXISTS links_au AFTER UPDATE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_link, url) VALUES('delete', old.rowid, old.short_link, old.url);
+    INSERT INTO links_fts(rowid, short_link, url) VALUES (new.rowid, new.short_link, new.url);
+END;
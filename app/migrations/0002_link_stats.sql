@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS link_stats (
+    short_link TEXT PRIMARY KEY NOT NULL REFERENCES links(short_link) ON UPDATE CASCADE ON DELETE CASCADE,
+    hits INTEGER NOT NULL DEFAULT 0,
+    last_accessed DATETIME
+);
+CREATE TABLE IF NOT EXISTS link_hits_daily (
+    short_link TEXT NOT NULL REFERENCES links(short_link) ON UPDATE CASCADE ON DELETE CASCADE,
+    day DATE NOT NULL,
+    hits INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (short_link, day)
+);
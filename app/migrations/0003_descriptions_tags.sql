@@ -0,0 +1,30 @@
+ALTER TABLE links ADD COLUMN description TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE tags (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE link_tags (
+    short_link TEXT NOT NULL REFERENCES links(short_link) ON UPDATE CASCADE ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (short_link, tag_id)
+);
+CREATE INDEX link_tags_tag_id ON link_tags(tag_id);
+
+-- Rebuild the search index with the description column
+DROP TRIGGER links_ai;
+DROP TRIGGER links_ad;
+DROP TRIGGER links_au;
+DROP TABLE links_fts;
+CREATE VIRTUAL TABLE links_fts USING fts5(short_link, url, description, content='links', content_rowid='rowid', tokenize='trigram');
+CREATE TRIGGER links_ai AFTER INSERT ON links BEGIN
+    INSERT INTO links_fts(rowid, short_link, url, description) VALUES (new.rowid, new.short_link, new.url, new.description);
+END;
+CREATE TRIGGER links_ad AFTER DELETE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_link, url, description) VALUES('delete', old.rowid, old.short_link, old.url, old.description);
+END;
+CREATE TRIGGER links_au AFTER UPDATE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_link, url, description) VALUES('delete', old.rowid, old.short_link, old.url, old.description);
+    INSERT INTO links_fts(rowid, short_link, url, description) VALUES (new.rowid, new.short_link, new.url, new.description);
+END;
+INSERT INTO links_fts(links_fts) VALUES('rebuild');
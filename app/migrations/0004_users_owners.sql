@@ -0,0 +1,13 @@
+ALTER TABLE links ADD COLUMN owner TEXT;
+
+CREATE TABLE users (
+    username TEXT PRIMARY KEY NOT NULL,
+    password_hash TEXT NOT NULL,
+    is_admin INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE sessions (
+    token TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL REFERENCES users(username) ON DELETE CASCADE,
+    expires_at DATETIME NOT NULL
+);
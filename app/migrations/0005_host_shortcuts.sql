@@ -0,0 +1,6 @@
+CREATE TABLE host_shortcuts (
+    host TEXT PRIMARY KEY NOT NULL,
+    url TEXT NOT NULL,
+    owner TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
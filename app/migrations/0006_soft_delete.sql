@@ -0,0 +1,28 @@
+ALTER TABLE links ADD COLUMN deleted_at DATETIME;
+CREATE INDEX links_deleted_at ON links(deleted_at);
+
+-- Links that are not in the trash. Everything that resolves, lists or
+-- searches links reads from here.
+CREATE VIEW live_links AS
+    SELECT rowid AS link_rowid, short_link, url, description, created_at, owner
+    FROM links WHERE deleted_at IS NULL;
+
+-- Rebuild the search index over live links only
+DROP TRIGGER links_ai;
+DROP TRIGGER links_ad;
+DROP TRIGGER links_au;
+DROP TABLE links_fts;
+CREATE VIRTUAL TABLE links_fts USING fts5(short_link, url, description, content='live_links', content_rowid='link_rowid', tokenize='trigram');
+CREATE TRIGGER links_ai AFTER INSERT ON links WHEN new.deleted_at IS NULL BEGIN
+    INSERT INTO links_fts(rowid, short_link, url, description) VALUES (new.rowid, new.short_link, new.url, new.description);
+END;
+CREATE TRIGGER links_ad AFTER DELETE ON links WHEN old.deleted_at IS NULL BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_link, url, description) VALUES('delete', old.rowid, old.short_link, old.url, old.description);
+END;
+CREATE TRIGGER links_au AFTER UPDATE ON links BEGIN
+    INSERT INTO links_fts(links_fts, rowid, short_link, url, description)
+        SELECT 'delete', old.rowid, old.short_link, old.url, old.description WHERE old.deleted_at IS NULL;
+    INSERT INTO links_fts(rowid, short_link, url, description)
+        SELECT new.rowid, new.short_link, new.url, new.description WHERE new.deleted_at IS NULL;
+END;
+INSERT INTO links_fts(links_fts) VALUES('rebuild');
@@ -0,0 +1,19 @@
+-- Bumped on every change to links or their tags, so in-memory copies of the
+-- links can tell that another process changed them.
+CREATE TABLE links_version (version INTEGER NOT NULL);
+INSERT INTO links_version (version) VALUES (0);
+CREATE TRIGGER links_version_ai AFTER INSERT ON links BEGIN
+    UPDATE links_version SET version = version + 1;
+END;
+CREATE TRIGGER links_version_au AFTER UPDATE ON links BEGIN
+    UPDATE links_version SET version = version + 1;
+END;
+CREATE TRIGGER links_version_ad AFTER DELETE ON links BEGIN
+    UPDATE links_version SET version = version + 1;
+END;
+CREATE TRIGGER link_tags_version_ai AFTER INSERT ON link_tags BEGIN
+    UPDATE links_version SET version = version + 1;
+END;
+CREATE TRIGGER link_tags_version_ad AFTER DELETE ON link_tags BEGIN
+    UPDATE links_version SET version = version + 1;
+END;
@@ -0,0 +1,13 @@
+-- Bumped on every change to host shortcuts, so in-memory copies of them can
+-- tell that another process changed them.
+CREATE TABLE host_shortcuts_version (version INTEGER NOT NULL);
+INSERT INTO host_shortcuts_version (version) VALUES (0);
+CREATE TRIGGER host_shortcuts_version_ai AFTER INSERT ON host_shortcuts BEGIN
+    UPDATE host_shortcuts_version SET version = version + 1;
+END;
+CREATE TRIGGER host_shortcuts_version_au AFTER UPDATE ON host_shortcuts BEGIN
+    UPDATE host_shortcuts_version SET version = version + 1;
+END;
+CREATE TRIGGER host_shortcuts_version_ad AFTER DELETE ON host_shortcuts BEGIN
+    UPDATE host_shortcuts_version SET version = version + 1;
+END;
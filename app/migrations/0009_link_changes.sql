@@ -0,0 +1,37 @@
+-- Which links each links_version bump touched, so in-memory copies of the
+-- links can re-read just those rather than reload them all. Rows are pruned
+-- after a while; a copy older than the oldest one left reloads instead.
+CREATE TABLE link_changes (
+    version INTEGER NOT NULL,
+    short_link TEXT NOT NULL,
+    changed_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX link_changes_version ON link_changes (version);
+
+DROP TRIGGER links_version_ai;
+DROP TRIGGER links_version_au;
+DROP TRIGGER links_version_ad;
+DROP TRIGGER link_tags_version_ai;
+DROP TRIGGER link_tags_version_ad;
+CREATE TRIGGER links_version_ai AFTER INSERT ON links BEGIN
+    UPDATE links_version SET version = version + 1;
+    INSERT INTO link_changes (version, short_link) SELECT version, new.short_link FROM links_version;
+END;
+CREATE TRIGGER links_version_au AFTER UPDATE ON links BEGIN
+    UPDATE links_version SET version = version + 1;
+    INSERT INTO link_changes (version, short_link) SELECT version, old.short_link FROM links_version;
+    INSERT INTO link_changes (version, short_link) SELECT version, new.short_link FROM links_version
+        WHERE new.short_link != old.short_link;
+END;
+CREATE TRIGGER links_version_ad AFTER DELETE ON links BEGIN
+    UPDATE links_version SET version = version + 1;
+    INSERT INTO link_changes (version, short_link) SELECT version, old.short_link FROM links_version;
+END;
+CREATE TRIGGER link_tags_version_ai AFTER INSERT ON link_tags BEGIN
+    UPDATE links_version SET version = version + 1;
+    INSERT INTO link_changes (version, short_link) SELECT version, new.short_link FROM links_version;
+END;
+CREATE TRIGGER link_tags_version_ad AFTER DELETE ON link_tags BEGIN
+    UPDATE links_version SET version = version + 1;
+    INSERT INTO link_changes (version, short_link) SELECT version, old.short_link FROM links_version;
+END;
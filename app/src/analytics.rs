@@ -0,0 +1,139 @@
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use sqlx::SqlitePool;
+use std::{collections::HashMap, time::Duration};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, warn};
+
+/// How often buffered hits are written to the database.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// Flush early once this many distinct links have pending hits.
+const MAX_PENDING: usize = 256;
+const CHANNEL_CAPACITY: usize = 1024;
+
+enum Command {
+    Hit(String, NaiveDateTime),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Default)]
+struct PendingHits {
+    hits: i64,
+    last_accessed: Option<NaiveDateTime>,
+    per_day: HashMap<NaiveDate, i64>,
+}
+
+/// Records link resolutions off the request path.
+///
+/// Hits are sent over a channel to a background task that aggregates them in
+/// memory and periodically writes them to `link_stats` and `link_hits_daily`
+/// in a single transaction.
+#[derive(Clone)]
+pub struct HitRecorder {
+    tx: mpsc::Sender<Command>,
+}
+
+impl HitRecorder {
+    /// Starts the background writer. Must be called from within a Tokio runtime.
+    pub fn spawn(pool: SqlitePool) -> Self {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(run(pool, rx));
+        Self { tx }
+    }
+
+    /// Queues a hit for `short_link`. Never blocks; hits are dropped if the
+    /// writer falls behind.
+    pub fn record(&self, short_link: &str) {
+        let now = Utc::now().naive_utc();
+        if self
+            .tx
+            .try_send(Command::Hit(short_link.to_string(), now))
+            .is_err()
+        {
+            warn!("Dropping hit for '{}': recorder is busy", short_link);
+        }
+    }
+
+    /// Writes all queued hits and waits until they are stored.
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(Command::Flush(done_tx)).await.is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+async fn run(pool: SqlitePool, mut rx: mpsc::Receiver<Command>) {
+    let mut pending: HashMap<String, PendingHits> = HashMap::new();
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            command = rx.recv() => match command {
+                Some(Command::Hit(short_link, at)) => {
+                    let entry = pending.entry(short_link).or_default();
+                    entry.hits += 1;
+                    entry.last_accessed = entry.last_accessed.max(Some(at));
+                    *entry.per_day.entry(at.date()).or_default() += 1;
+                    if pending.len() >= MAX_PENDING {
+                        write(&pool, &mut pending).await;
+                    }
+                }
+                Some(Command::Flush(done)) => {
+                    write(&pool, &mut pending).await;
+                    let _ = done.send(());
+                }
+                None => {
+                    write(&pool, &mut pending).await;
+                    return;
+                }
+            },
+            _ = interval.tick() => write(&pool, &mut pending).await,
+        }
+    }
+}
+
+async fn write(pool: &SqlitePool, pending: &mut HashMap<String, PendingHits>) {
+    if pending.is_empty() {
+        return;
+    }
+    if let Err(e) = write_batch(pool, pending).await {
+        error!("Failed to record link hits: {:?}", e);
+    }
+    pending.clear();
+}
+
+async fn write_batch(
+    pool: &SqlitePool,
+    pending: &HashMap<String, PendingHits>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (short_link, stats) in pending {
+        // Links deleted since the hit was recorded are skipped by the EXISTS check.
+        sqlx::query(
+            "INSERT INTO link_stats (short_link, hits, last_accessed)
+             SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM links WHERE short_link = ?1)
+             ON CONFLICT(short_link) DO UPDATE SET
+                 hits = hits + excluded.hits,
+                 last_accessed = MAX(COALESCE(last_accessed, excluded.last_accessed), excluded.last_accessed)",
+        )
+        .bind(short_link)
+        .bind(stats.hits)
+        .bind(stats.last_accessed)
+        .execute(&mut *tx)
+        .await?;
+
+        for (day, hits) in &stats.per_day {
+            sqlx::query(
+                "INSERT INTO link_hits_daily (short_link, day, hits)
+                 SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM links WHERE short_link = ?1)
+                 ON CONFLICT(short_link, day) DO UPDATE SET hits = hits + excluded.hits",
+            )
+            .bind(short_link)
+            .bind(day)
+            .bind(hits)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await
+}
@@ -0,0 +1,107 @@
+use axum::{
+    Form, Json,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{HeaderMap, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::{AppError, ErrorInfo};
+
+fn is_json(value: Option<&header::HeaderValue>) -> bool {
+    value
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"))
+}
+
+/// Returns true when the client asked for JSON, either explicitly through
+/// `Accept` or implicitly by sending a JSON body without an `Accept` preference.
+pub fn wants_json(headers: &HeaderMap) -> bool {
+    let accept = headers.get(header::ACCEPT);
+    if is_json(accept) {
+        return true;
+    }
+    let no_preference = accept
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| v.trim() == "*/*");
+    no_preference && is_json(headers.get(header::CONTENT_TYPE))
+}
+
+/// The representation a handler should respond with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseFormat {
+    Html,
+    Json,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ResponseFormat {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(if wants_json(&parts.headers) {
+            ResponseFormat::Json
+        } else {
+            ResponseFormat::Html
+        })
+    }
+}
+
+/// A request body accepted either as a form (HTMX, plain HTML forms) or as
+/// JSON, depending on `Content-Type`.
+pub struct Payload<T>(pub T);
+
+impl<S, T> FromRequest<S> for Payload<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if is_json(req.headers().get(header::CONTENT_TYPE)) {
+            let Json(value) = Json::<T>::from_request(req, state)
+                .await
+                .map_err(|e| AppError(e.status(), anyhow::anyhow!(e.body_text())))?;
+            Ok(Payload(value))
+        } else {
+            let Form(value) = Form::<T>::from_request(req, state)
+                .await
+                .map_err(|e| AppError(e.status(), anyhow::anyhow!(e.body_text())))?;
+            Ok(Payload(value))
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorEnvelope<'a> {
+    error: ErrorBody<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    status: u16,
+    message: &'a str,
+}
+
+/// Replaces the HTMX error fragment produced by [`AppError`] with a JSON
+/// envelope carrying the real status code when the client asked for JSON.
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    let json = wants_json(request.headers());
+    let response = next.run(request).await;
+    if !json {
+        return response;
+    }
+    match response.extensions().get::<ErrorInfo>() {
+        Some(info) => {
+            let body = ErrorEnvelope {
+                error: ErrorBody {
+                    status: info.status.as_u16(),
+                    message: &info.message,
+                },
+            };
+            (info.status, Json(body)).into_response()
+        }
+        None => response,
+    }
+}
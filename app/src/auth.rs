@@ -0,0 +1,346 @@
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{HeaderMap, StatusCode, header, request::Parts},
+};
+use chrono::{Duration, Utc};
+use rand::{RngCore, rngs::OsRng};
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{error::AppError, models::Link, state::AppState};
+
+pub const SESSION_COOKIE: &str = "to_links_session";
+const SESSION_TTL_DAYS: i64 = 30;
+
+/// Where user identities come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// No identities; everyone may change every link.
+    #[default]
+    Off,
+    /// Trust a username header set by the reverse proxy.
+    Proxy,
+    /// Built-in accounts with session cookies.
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthSettings {
+    pub mode: AuthMode,
+    /// Header carrying the username in [`AuthMode::Proxy`].
+    pub proxy_header: String,
+    /// Usernames with admin rights, in addition to local admin accounts.
+    pub admins: Vec<String>,
+    /// Peers besides loopback whose `proxy_header` is believed.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            mode: AuthMode::Off,
+            proxy_header: "X-Remote-User".to_string(),
+            admins: Vec::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+impl AuthSettings {
+    /// Whether a request from `peer` may name its user in `proxy_header`:
+    /// only the reverse proxy can, so clients reaching the app directly can't
+    /// claim to be anyone.
+    pub fn trusts(&self, peer: IpAddr) -> bool {
+        let peer = peer.to_canonical();
+        peer.is_loopback() || self.trusted_proxies.contains(&peer)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct User {
+    pub username: String,
+    pub is_admin: bool,
+}
+
+/// The identity behind a request. Anonymous viewers may still resolve links;
+/// changes are checked with the `require_*` methods, which always pass when
+/// authentication is off.
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub user: Option<User>,
+    pub mode: AuthMode,
+}
+
+impl Viewer {
+    fn enforced(&self) -> bool {
+        self.mode != AuthMode::Off
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.user.as_ref().map(|u| u.username.as_str())
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.user.as_ref().is_some_and(|u| u.is_admin)
+    }
+
+    /// Whether [`Viewer::require_owner_of`] would let this viewer change
+    /// something owned by `owner`, once signed in.
+    pub fn may_change(&self, owner: Option<&str>) -> bool {
+        !self.enforced() || self.is_admin() || owner == self.username()
+    }
+
+    pub fn require_user(&self) -> Result<(), AppError> {
+        if self.enforced() && self.user.is_none() {
+            return Err(AppError(
+                StatusCode::UNAUTHORIZED,
+                anyhow::anyhow!("Sign in to change links"),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        self.require_user()?;
+        if self.enforced() && !self.is_admin() {
+            return Err(AppError(
+                StatusCode::FORBIDDEN,
+                anyhow::anyhow!("Only admins can do this"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Only the owner of a link or an admin may edit or delete it.
+    pub fn require_owner(&self, link: &Link) -> Result<(), AppError> {
+        self.require_owner_of(&link.short_link, link.owner.as_deref())
+    }
+
+    /// Like [`Viewer::require_owner`], for anything named `name` owned by `owner`.
+    pub fn require_owner_of(&self, name: &str, owner: Option<&str>) -> Result<(), AppError> {
+        self.require_user()?;
+        if !self.may_change(owner) {
+            return Err(AppError(
+                StatusCode::FORBIDDEN,
+                anyhow::anyhow!("Only the owner of '{}' or an admin can change it", name),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromRequestParts<AppState> for Viewer {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let auth = &state.settings.auth;
+        let user = match auth.mode {
+            AuthMode::Off => None,
+            AuthMode::Proxy => ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+                .await
+                .ok()
+                .filter(|ConnectInfo(peer)| auth.trusts(peer.ip()))
+                .and_then(|_| parts.headers.get(auth.proxy_header.as_str()))
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|username| User {
+                    username: username.to_string(),
+                    is_admin: false,
+                }),
+            AuthMode::Local => match cookie(&parts.headers, SESSION_COOKIE) {
+                Some(token) => session_user(&state.pool, token).await.map_err(|_| {
+                    AppError(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        anyhow::anyhow!("Failed to load session"),
+                    )
+                })?,
+                None => None,
+            },
+        };
+        let user = user.map(|mut u| {
+            u.is_admin |= auth.admins.contains(&u.username);
+            u
+        });
+        Ok(Viewer {
+            user,
+            mode: auth.mode,
+        })
+    }
+}
+
+/// Reads a cookie value from the request headers.
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+pub fn session_cookie(token: &str) -> String {
+    format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        SESSION_COOKIE,
+        token,
+        SESSION_TTL_DAYS * 24 * 60 * 60
+    )
+}
+
+pub fn clear_session_cookie() -> String {
+    format!(
+        "{}=; Path=/; HttpOnly; SameSite=Lax; Max-Age=0",
+        SESSION_COOKIE
+    )
+}
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+pub async fn create_user(
+    pool: &SqlitePool,
+    username: &str,
+    password: &str,
+    is_admin: bool,
+) -> anyhow::Result<()> {
+    if username.is_empty() || username.chars().any(|c| c.is_whitespace()) {
+        anyhow::bail!("Invalid username");
+    }
+    if password.is_empty() {
+        anyhow::bail!("Password cannot be empty");
+    }
+    let hash = hash_password(password)?;
+    sqlx::query("INSERT INTO users (username, password_hash, is_admin) VALUES (?, ?, ?)")
+        .bind(username)
+        .bind(hash)
+        .bind(is_admin)
+        .execute(pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                anyhow::anyhow!("User '{}' already exists", username)
+            }
+            e => e.into(),
+        })?;
+    Ok(())
+}
+
+/// Deletes a user and their sessions, returning whether they existed.
+pub async fn delete_user(pool: &SqlitePool, username: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM users WHERE username = ?")
+        .bind(username)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_users(pool: &SqlitePool) -> Result<Vec<User>, sqlx::Error> {
+    sqlx::query_as("SELECT username, is_admin FROM users ORDER BY username")
+        .fetch_all(pool)
+        .await
+}
+
+/// Checks the credentials and opens a session, returning its token.
+pub async fn login(
+    pool: &SqlitePool,
+    username: &str,
+    password: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let hash: Option<(String,)> =
+        sqlx::query_as("SELECT password_hash FROM users WHERE username = ?")
+            .bind(username)
+            .fetch_optional(pool)
+            .await?;
+    let Some((hash,)) = hash else {
+        return Ok(None);
+    };
+    if !verify_password(password, &hash) {
+        return Ok(None);
+    }
+
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let expires_at = Utc::now().naive_utc() + Duration::days(SESSION_TTL_DAYS);
+    sqlx::query("DELETE FROM sessions WHERE expires_at <= ?")
+        .bind(Utc::now().naive_utc())
+        .execute(pool)
+        .await?;
+    sqlx::query("INSERT INTO sessions (token, username, expires_at) VALUES (?, ?, ?)")
+        .bind(&token)
+        .bind(username)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+    Ok(Some(token))
+}
+
+pub async fn logout(pool: &SqlitePool, token: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE token = ?")
+        .bind(token)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+async fn session_user(pool: &SqlitePool, token: &str) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT u.username, u.is_admin FROM sessions s JOIN users u ON u.username = s.username
+         WHERE s.token = ? AND s.expires_at > ?",
+    )
+    .bind(token)
+    .bind(Utc::now().naive_utc())
+    .fetch_optional(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hashing() {
+        let hash = hash_password("hunter2").unwrap();
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+        assert!(!verify_password("hunter2", "not a hash"));
+    }
+
+    #[test]
+    fn test_cookie_parsing() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            "theme=dark; to_links_session=abc123".parse().unwrap(),
+        );
+        assert_eq!(cookie(&headers, SESSION_COOKIE), Some("abc123"));
+        assert_eq!(cookie(&headers, "missing"), None);
+    }
+}
@@ -0,0 +1,260 @@
+use serde::Serialize;
+use sqlx::SqliteConnection;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+};
+
+use crate::{url_policy::UrlPolicy, url_template};
+
+/// Links followed server-side for one request unless configured otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 5;
+
+/// A link visited while resolving a chain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Hop {
+    pub short_link: String,
+    /// The link's target with the request's extra segments substituted.
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChainEnd {
+    /// The last hop leaves the app (or names no link): redirect there.
+    Resolved { url: String },
+    /// The last hop is a link already visited.
+    Loop,
+    /// More links than allowed were followed.
+    TooLong,
+}
+
+/// The links a short link path resolves through, e.g. `docs` →
+/// `http://to/wiki` → `wiki` → `https://wiki.example`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Chain {
+    pub hops: Vec<Hop>,
+    pub end: ChainEnd,
+}
+
+impl Chain {
+    /// A single link redirecting straight out of the app.
+    pub fn is_direct(&self) -> bool {
+        self.hops.len() == 1 && matches!(self.end, ChainEnd::Resolved { .. })
+    }
+
+    /// `a → b → c`, for messages.
+    pub fn describe(&self) -> String {
+        self.hops
+            .iter()
+            .map(|h| h.short_link.as_str())
+            .collect::<Vec<_>>()
+            .join(" → ")
+    }
+}
+
+/// A link about to be saved, resolved in place of the stored rows.
+#[derive(Debug, Clone, Copy)]
+pub struct Pending<'a> {
+    pub short_link: &'a str,
+    pub url: &'a str,
+    /// The link's current name when it is being renamed.
+    pub replaces: Option<&'a str>,
+}
+
+/// Where chains find the stored links: the database, or the in-memory
+/// [`LinkIndex`](crate::index::LinkIndex).
+pub trait Links {
+    /// The targets of those of `names` that are live links.
+    fn targets(
+        &mut self,
+        names: &[String],
+    ) -> impl Future<Output = Result<HashMap<String, String>, sqlx::Error>> + Send;
+
+    /// The target of every live link.
+    fn all_targets(
+        &mut self,
+    ) -> impl Future<Output = Result<HashMap<String, String>, sqlx::Error>> + Send;
+}
+
+impl Links for HashMap<String, String> {
+    async fn targets(&mut self, names: &[String]) -> Result<HashMap<String, String>, sqlx::Error> {
+        Ok(names
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.get(name)?.clone())))
+            .collect())
+    }
+
+    async fn all_targets(&mut self) -> Result<HashMap<String, String>, sqlx::Error> {
+        Ok(self.clone())
+    }
+}
+
+impl Links for SqliteConnection {
+    async fn targets(&mut self, names: &[String]) -> Result<HashMap<String, String>, sqlx::Error> {
+        let placeholders = vec!["?"; names.len()].join(", ");
+        let sql = format!(
+            "SELECT short_link, url FROM live_links WHERE short_link IN ({})",
+            placeholders
+        );
+        let mut query = sqlx::query_as::<_, (String, String)>(&sql);
+        for name in names {
+            query = query.bind(name);
+        }
+        Ok(query.fetch_all(self).await?.into_iter().collect())
+    }
+
+    async fn all_targets(&mut self) -> Result<HashMap<String, String>, sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT short_link, url FROM live_links")
+            .fetch_all(self)
+            .await?;
+        Ok(rows.into_iter().collect())
+    }
+}
+
+/// Resolves `path` to the most specific matching link, like `/{short_link}`.
+async fn resolve(
+    links: &mut impl Links,
+    path: &str,
+    pending: Option<Pending<'_>>,
+) -> Result<Option<Hop>, sqlx::Error> {
+    let candidates = url_template::candidates(path);
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let names: Vec<String> = candidates.iter().map(|(name, _)| name.clone()).collect();
+    let stored = links.targets(&names).await?;
+
+    Ok(candidates.iter().find_map(|(name, args)| {
+        let url = match pending {
+            Some(p) if p.short_link == name => Some(p.url),
+            Some(p) if p.replaces == Some(name.as_str()) => None,
+            _ => stored.get(name).map(String::as_str),
+        }?;
+        Some(Hop {
+            short_link: name.clone(),
+            url: url_template::expand(url, args),
+        })
+    }))
+}
+
+/// Follows `path` through links pointing at other links on the app's own
+/// hosts, up to `max_depth` links. Returns `None` when no link matches.
+pub async fn follow(
+    links: &mut impl Links,
+    urls: &UrlPolicy,
+    path: &str,
+    max_depth: usize,
+    pending: Option<Pending<'_>>,
+) -> Result<Option<Chain>, sqlx::Error> {
+    let mut hops: Vec<Hop> = Vec::new();
+    let mut path = path.to_string();
+    loop {
+        let Some(hop) = resolve(links, &path, pending).await? else {
+            // An internal URL that names no link is a regular target.
+            let Some(last) = hops.last() else {
+                return Ok(None);
+            };
+            let url = last.url.clone();
+            return Ok(Some(Chain {
+                hops,
+                end: ChainEnd::Resolved { url },
+            }));
+        };
+        let looped = hops.iter().any(|h| h.short_link == hop.short_link);
+        let next = urls.internal_path(&hop.url);
+        let url = hop.url.clone();
+        hops.push(hop);
+
+        let end = if looped {
+            ChainEnd::Loop
+        } else if hops.len() > max_depth {
+            ChainEnd::TooLong
+        } else if let Some(next) = next {
+            path = next;
+            continue;
+        } else {
+            ChainEnd::Resolved { url }
+        };
+        return Ok(Some(Chain { hops, end }));
+    }
+}
+
+/// Checks that the chains through `pending` would still end somewhere:
+/// without a loop and within `max_depth` links, both from `pending` itself
+/// and from every link that leads to it.
+pub async fn check_pending(
+    links: &mut impl Links,
+    urls: &UrlPolicy,
+    pending: Pending<'_>,
+    max_depth: usize,
+) -> Result<Result<(), String>, sqlx::Error> {
+    if let Some(chain) = follow(links, urls, pending.short_link, max_depth, Some(pending)).await?
+        && let Err(e) = check_end(&chain, max_depth)
+    {
+        return Ok(Err(e));
+    }
+
+    let mut all = links.all_targets().await?;
+    for name in upstream(&all, urls, pending) {
+        if let Some(chain) = follow(&mut all, urls, &name, max_depth, Some(pending)).await?
+            && let Err(e) = check_end(&chain, max_depth)
+        {
+            return Ok(Err(e));
+        }
+    }
+    Ok(Ok(()))
+}
+
+fn check_end(chain: &Chain, max_depth: usize) -> Result<(), String> {
+    match chain.end {
+        ChainEnd::Resolved { .. } => Ok(()),
+        ChainEnd::Loop => Err(format!(
+            "Link would create a redirect loop: {}",
+            chain.describe()
+        )),
+        ChainEnd::TooLong => Err(format!(
+            "Link would create a redirect chain longer than {} links: {}",
+            max_depth,
+            chain.describe()
+        )),
+    }
+}
+
+/// The links whose chains would pass through `pending`, nearest first.
+fn upstream(all: &HashMap<String, String>, urls: &UrlPolicy, pending: Pending<'_>) -> Vec<String> {
+    let live = |name: &str| {
+        name == pending.short_link || (pending.replaces != Some(name) && all.contains_key(name))
+    };
+    // Which link each internal target resolves to, reversed.
+    let mut callers: HashMap<String, Vec<&str>> = HashMap::new();
+    for (name, url) in all {
+        if name == pending.short_link || pending.replaces == Some(name.as_str()) {
+            continue;
+        }
+        let Some(path) = urls.internal_path(url) else {
+            continue;
+        };
+        if let Some((target, _)) = url_template::candidates(&path)
+            .into_iter()
+            .find(|(target, _)| live(target))
+        {
+            callers.entry(target).or_default().push(name);
+        }
+    }
+
+    let mut found = Vec::new();
+    let mut seen = HashSet::from([pending.short_link]);
+    let mut next = vec![pending.short_link];
+    while !next.is_empty() {
+        let mut names: Vec<&str> = next
+            .iter()
+            .flat_map(|target| callers.get(*target).into_iter().flatten().copied())
+            .filter(|name| seen.insert(name))
+            .collect();
+        names.sort_unstable();
+        found.extend(names.iter().map(|name| name.to_string()));
+        next = names;
+    }
+    found
+}
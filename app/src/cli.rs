@@ -0,0 +1,908 @@
+use clap::{Parser, Subcommand};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+use crate::{
+    analytics::HitRecorder,
+    auth::{self, AuthMode},
+    chain,
+    config::{Config, LogFormat},
+    connect_db, connect_db_with, create_router,
+    dns::{self, DnsServer, DnsSettings},
+    health,
+    hosts::normalize_host,
+    index::LinkIndex,
+    migrations,
+    models::{NewHostShortcut, NewLink, SearchParams, SortOrder, normalize_tags},
+    queries,
+    render::{self, ConfigKind, RenderContext},
+    sd_notify, setup_db,
+    state::{AppState, Settings},
+    transfer::{self, ConflictPolicy, Format},
+    trash,
+    url_policy::UrlPolicy,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    /// Config file (default: ~/.config/to-links/config.toml, then ~/.local/share/to-links/config.toml)
+    #[arg(long, global = true, env = "TO_LINKS_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Path to the SQLite database (default: ~/.local/share/to-links/app.db)
+    #[arg(long, global = true)]
+    pub db: Option<String>,
+    #[command(flatten)]
+    pub serve: ServeArgs,
+    /// Defaults to `serve` when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Options for `serve`; also accepted without a subcommand. Each one
+/// overrides the config file and `TO_LINKS_*` variables when given.
+#[derive(clap::Args, Debug, Default)]
+pub struct ServeArgs {
+    /// Address the web server listens on [default: 0.0.0.0]
+    #[arg(long, global = true)]
+    pub bind: Option<IpAddr>,
+    /// Port for the web server [default: 3000]
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+    /// Maximum number of database connections [default: 5]
+    #[arg(long, global = true)]
+    pub pool_size: Option<u32>,
+    #[arg(long, global = true, value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// Redirect a missing short link to the only existing link one typo away
+    #[arg(long, global = true, num_args = 0..=1, default_missing_value = "true")]
+    pub auto_redirect_typos: Option<bool>,
+    /// Refuse this short link name, like the app's own routes; repeat for several
+    #[arg(long = "reserve", global = true)]
+    pub reserved_names: Vec<String>,
+    /// URL scheme link targets may use; repeat for several [default: http, https]
+    #[arg(long = "allow-scheme", global = true)]
+    pub allowed_schemes: Vec<String>,
+    /// Links followed server-side when a link points at another one [default: 5]
+    #[arg(long, global = true)]
+    pub max_chain_depth: Option<usize>,
+    /// Days a deleted link stays in the trash; 0 keeps it until purged by hand [default: 30]
+    #[arg(long, global = true)]
+    pub trash_retention_days: Option<u32>,
+    /// How users are identified; `off` lets anyone change any link
+    #[arg(long, global = true, value_enum)]
+    pub auth: Option<AuthMode>,
+    /// Header carrying the username when `--auth proxy` is used [default: X-Remote-User]
+    #[arg(long, global = true)]
+    pub auth_header: Option<String>,
+    /// User allowed to change every link; repeat for several
+    #[arg(long = "admin-user", global = true)]
+    pub admins: Vec<String>,
+    /// Proxy address besides loopback allowed to set the auth header; repeat for several
+    #[arg(long = "trusted-proxy", global = true)]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Also answer DNS queries on this address (e.g. 0.0.0.0:53)
+    #[arg(long, global = true)]
+    pub dns_listen: Option<SocketAddr>,
+    /// Hostname answered by the DNS responder, with or without `.lan`; repeat for several [default: to]
+    #[arg(long = "dns-host", global = true)]
+    pub dns_hosts: Vec<String>,
+    /// Address returned for those hostnames (default: this host's LAN address)
+    #[arg(long = "dns-address", global = true)]
+    pub dns_addresses: Vec<IpAddr>,
+    /// Resolver for all other names; without it they are refused
+    #[arg(long, global = true)]
+    pub dns_upstream: Option<SocketAddr>,
+}
+
+impl ServeArgs {
+    /// Overrides `config` with the flags that were given.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(pool_size) = self.pool_size {
+            config.pool_size = pool_size;
+        }
+        if let Some(log_format) = self.log_format {
+            config.log_format = log_format;
+        }
+        if let Some(auto_redirect_typos) = self.auto_redirect_typos {
+            config.auto_redirect_typos = auto_redirect_typos;
+        }
+        if !self.reserved_names.is_empty() {
+            config.reserved_names = self.reserved_names.clone();
+        }
+        if !self.allowed_schemes.is_empty() {
+            config.allowed_schemes = self.allowed_schemes.clone();
+        }
+        if let Some(depth) = self.max_chain_depth {
+            config.max_chain_depth = depth;
+        }
+        if let Some(days) = self.trash_retention_days {
+            config.trash_retention_days = days;
+        }
+        if let Some(auth) = self.auth {
+            config.auth.mode = auth;
+        }
+        if let Some(header) = &self.auth_header {
+            config.auth.header = header.clone();
+        }
+        if !self.admins.is_empty() {
+            config.auth.admins = self.admins.clone();
+        }
+        if !self.trusted_proxies.is_empty() {
+            config.auth.trusted_proxies = self.trusted_proxies.clone();
+        }
+        if let Some(listen) = self.dns_listen {
+            config.dns.listen = Some(listen);
+        }
+        if !self.dns_hosts.is_empty() {
+            config.dns.hosts = self.dns_hosts.clone();
+        }
+        if !self.dns_addresses.is_empty() {
+            config.dns.addresses = self.dns_addresses.clone();
+        }
+        if let Some(upstream) = self.dns_upstream {
+            config.dns.upstream = Some(upstream);
+        }
+    }
+
+    /// The command line that reproduces the flags that were given.
+    pub fn to_args(&self) -> Vec<String> {
+        fn name<T: clap::ValueEnum>(value: &T) -> String {
+            value.to_possible_value().unwrap().get_name().to_string()
+        }
+        let mut args = Vec::new();
+        let mut push = |flag: &str, value: String| args.extend([flag.to_string(), value]);
+        if let Some(bind) = self.bind {
+            push("--bind", bind.to_string());
+        }
+        if let Some(port) = self.port {
+            push("--port", port.to_string());
+        }
+        if let Some(pool_size) = self.pool_size {
+            push("--pool-size", pool_size.to_string());
+        }
+        if let Some(log_format) = &self.log_format {
+            push("--log-format", name(log_format));
+        }
+        if let Some(auto_redirect_typos) = self.auto_redirect_typos {
+            push("--auto-redirect-typos", auto_redirect_typos.to_string());
+        }
+        for reserved in &self.reserved_names {
+            push("--reserve", reserved.clone());
+        }
+        for scheme in &self.allowed_schemes {
+            push("--allow-scheme", scheme.clone());
+        }
+        if let Some(depth) = self.max_chain_depth {
+            push("--max-chain-depth", depth.to_string());
+        }
+        if let Some(days) = self.trash_retention_days {
+            push("--trash-retention-days", days.to_string());
+        }
+        if let Some(auth) = &self.auth {
+            push("--auth", name(auth));
+        }
+        if let Some(header) = &self.auth_header {
+            push("--auth-header", header.clone());
+        }
+        for admin in &self.admins {
+            push("--admin-user", admin.clone());
+        }
+        for proxy in &self.trusted_proxies {
+            push("--trusted-proxy", proxy.to_string());
+        }
+        if let Some(listen) = self.dns_listen {
+            push("--dns-listen", listen.to_string());
+        }
+        for host in &self.dns_hosts {
+            push("--dns-host", host.clone());
+        }
+        for address in &self.dns_addresses {
+            push("--dns-address", address.to_string());
+        }
+        if let Some(upstream) = self.dns_upstream {
+            push("--dns-upstream", upstream.to_string());
+        }
+        args
+    }
+}
+
+/// Builds the effective config: defaults, then the config file, then
+/// `TO_LINKS_*` variables, then `args`. Also returns the file that was read.
+pub fn load_config(args: &Args) -> anyhow::Result<(Config, Option<PathBuf>)> {
+    let path = Config::locate(args.config.as_deref());
+    let mut config = match &path {
+        Some(path) => Config::from_file(path)?,
+        None => Config::default(),
+    };
+    config
+        .apply_env(std::env::vars_os().filter_map(|(key, value)| {
+            Some((key.into_string().ok()?, value.into_string().ok()?))
+        }))?;
+    if let Some(db) = &args.db {
+        config.db = Some(db.clone());
+    }
+    args.serve.apply(&mut config);
+    Ok((config, path))
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Run the web server
+    Serve,
+    /// Create a short link
+    Add {
+        short_link: String,
+        url: String,
+        #[arg(long, short, default_value = "")]
+        description: String,
+        /// Tag to attach; repeat for several
+        #[arg(long = "tag", short)]
+        tags: Vec<String>,
+        /// User recorded as the owner of the link
+        #[arg(long)]
+        owner: Option<String>,
+    },
+    /// Move a short link to the trash
+    Rm { short_link: String },
+    /// List links, or fuzzy-search them when a query is given
+    #[command(visible_alias = "search")]
+    Ls {
+        query: Option<String>,
+        #[arg(long, value_enum, default_value = "recent")]
+        sort: SortOrder,
+        #[arg(long, default_value = "1")]
+        page: u32,
+        #[arg(long, default_value = "20")]
+        limit: u32,
+    },
+    /// Show the details of a short link
+    Show { short_link: String },
+    /// Apply pending schema migrations and exit
+    Migrate,
+    /// Write all links to a file (or stdout)
+    Export {
+        /// Output format; inferred from the output file name when omitted
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// Output file; defaults to stdout
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Load links from a file (or stdin with `-`)
+    Import {
+        file: String,
+        /// Input format; inferred from the file name when omitted
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+        /// What to do with short links that already exist
+        #[arg(long, value_enum, default_value = "skip")]
+        policy: ConflictPolicy,
+        /// Report what would change without saving anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Print an nginx, dnsmasq or systemd config for the current settings
+    RenderConfig {
+        #[arg(value_enum)]
+        kind: ConfigKind,
+        /// Address nginx uses to reach the app
+        #[arg(long, default_value = "127.0.0.1")]
+        app_host: String,
+        /// Address the hostnames resolve to (default: first --dns-address, else this host's LAN address)
+        #[arg(long)]
+        address: Option<IpAddr>,
+        /// Path of the binary in the systemd unit (default: this binary)
+        #[arg(long)]
+        exec: Option<String>,
+        /// Account the systemd service runs as
+        #[arg(long)]
+        user: Option<String>,
+        /// Output file; defaults to stdout
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// List, restore or permanently delete links in the trash
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+    /// Manage host shortcuts (whole hostnames such as `maps`)
+    Host {
+        #[command(subcommand)]
+        command: HostCommand,
+    },
+    /// Manage local accounts used by `--auth local`
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Validate the config file and environment, and print the effective config
+    Check,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TrashCommand {
+    /// List deleted links, most recent first
+    Ls,
+    /// Take a link back out of the trash
+    Restore { short_link: String },
+    /// Permanently delete a link, or every link past the retention period
+    Purge { short_link: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HostCommand {
+    /// Redirect every request for `host` (and `host.lan`) to `url`
+    Add { host: String, url: String },
+    /// Delete a host shortcut
+    Rm { host: String },
+    /// List host shortcuts
+    Ls,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum UserCommand {
+    /// Create an account; the password is read from stdin unless given
+    Add {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+        /// Allow the user to change every link
+        #[arg(long)]
+        admin: bool,
+    },
+    /// Delete an account and its sessions
+    Rm { username: String },
+    /// List accounts
+    Ls,
+}
+
+/// Resolves `--db`, defaulting to `~/.local/share/to-links/app.db`.
+pub fn resolve_db_path(db: Option<String>) -> anyhow::Result<String> {
+    match db {
+        Some(path) => Ok(path),
+        None => {
+            let home_dir =
+                dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
+            let default_path = home_dir.join(".local/share/to-links");
+            std::fs::create_dir_all(&default_path)?;
+            Ok(default_path.join("app.db").to_string_lossy().to_string())
+        }
+    }
+}
+
+/// Runs `args` with the config produced by [`load_config`], read from
+/// `config_path` if set.
+pub async fn run(args: Args, config: Config, config_path: Option<PathBuf>) -> anyhow::Result<()> {
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = &args.command
+    {
+        match &config_path {
+            Some(path) => println!("# Loaded from {}", path.display()),
+            None => println!("# No config file found; using defaults"),
+        }
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
+
+    let db_path = resolve_db_path(config.db.clone())?;
+    info!("Using database at: {}", db_path);
+
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(&db_path, &config).await,
+        Command::Add {
+            short_link,
+            url,
+            description,
+            tags,
+            owner,
+        } => {
+            let link = NewLink {
+                short_link,
+                url,
+                description,
+                tags: normalize_tags(tags),
+            };
+            add(&db_path, link, owner.as_deref(), &config.settings()).await
+        }
+        Command::Rm { short_link } => rm(&db_path, &short_link).await,
+        Command::Ls {
+            query,
+            sort,
+            page,
+            limit,
+        } => {
+            let params = SearchParams {
+                q: query,
+                page: page.max(1),
+                limit: limit.max(1),
+                sort,
+            };
+            ls(&db_path, &params).await
+        }
+        Command::Show { short_link } => show(&db_path, &short_link).await,
+        Command::Migrate => migrate(&db_path).await,
+        Command::Export { format, output } => export(&db_path, format, output).await,
+        Command::Import {
+            file,
+            format,
+            policy,
+            dry_run,
+        } => {
+            let settings = config.settings();
+            import(&db_path, &file, format, policy, dry_run, &settings).await
+        }
+        Command::RenderConfig {
+            kind,
+            app_host,
+            address,
+            exec,
+            user,
+            output,
+        } => {
+            let address = match address.or(config.dns.addresses.first().copied()) {
+                Some(address) => address,
+                None => dns::detect_address().map_err(|e| {
+                    anyhow::anyhow!(
+                        "Could not detect this host's address ({}); pass --address",
+                        e
+                    )
+                })?,
+            };
+            let exec = match exec {
+                Some(exec) => exec,
+                None => std::env::current_exe()?.to_string_lossy().to_string(),
+            };
+            // The unit reads the same config file, plus the flags given here.
+            let mut serve_args = Vec::new();
+            if let Some(path) = &config_path {
+                let path = std::path::absolute(path)?;
+                serve_args.extend(["--config".to_string(), path.to_string_lossy().to_string()]);
+            }
+            serve_args.extend(["--db".to_string(), db_path.clone()]);
+            serve_args.extend(args.serve.to_args());
+            let auth = config.auth_settings();
+            let ctx = RenderContext {
+                hostnames: config.dns.hosts.iter().map(|h| normalize_host(h)).collect(),
+                address,
+                app_host,
+                port: config.port,
+                exec,
+                user,
+                serve_args,
+                auth_mode: auth.mode,
+                auth_header: auth.proxy_header,
+            };
+            render_config(&db_path, kind, ctx, output).await
+        }
+        Command::Config { .. } => unreachable!("handled above"),
+        Command::Trash { command } => trash(&db_path, command, &config.settings()).await,
+        Command::Host { command } => host(&db_path, command, &config.settings().urls).await,
+        Command::User { command } => user(&db_path, command).await,
+    }
+}
+
+async fn add(
+    db_path: &str,
+    mut link: NewLink,
+    owner: Option<&str>,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    link.validate().map_err(|e| anyhow::anyhow!(e))?;
+    settings
+        .reserved
+        .check(&link.short_link)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    link.url = settings
+        .urls
+        .check_link(&link.short_link, &link.url)
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let pool = setup_db(db_path).await?;
+    let pending = chain::Pending {
+        short_link: &link.short_link,
+        url: &link.url,
+        replaces: None,
+    };
+    chain::check_pending(
+        &mut *pool.acquire().await?,
+        &settings.urls,
+        pending,
+        settings.max_chain_depth,
+    )
+    .await?
+    .map_err(|e| anyhow::anyhow!(e))?;
+    queries::insert_link(&pool, &link, owner)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                anyhow::anyhow!("Short link '{}' already exists", link.short_link)
+            }
+            e => e.into(),
+        })?;
+    println!("Created {} -> {}", link.short_link, link.url);
+    Ok(())
+}
+
+async fn rm(db_path: &str, short_link: &str) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    if !queries::delete_link(&pool, short_link).await? {
+        anyhow::bail!("Link not found: {}", short_link);
+    }
+    println!("Moved {} to the trash", short_link);
+    Ok(())
+}
+
+async fn trash(db_path: &str, command: TrashCommand, settings: &Settings) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    match command {
+        TrashCommand::Ls => {
+            let links = queries::list_trash(&pool).await?;
+            let width = links
+                .iter()
+                .map(|l| l.short_link.chars().count())
+                .max()
+                .unwrap_or(0);
+            for link in &links {
+                println!(
+                    "{:width$}  {}  (deleted {})",
+                    link.short_link,
+                    link.url,
+                    link.deleted_at.format("%Y-%m-%d %H:%M"),
+                    width = width
+                );
+            }
+        }
+        TrashCommand::Restore { short_link } => {
+            let link = queries::fetch_trashed_link(&pool, &short_link)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Link not found in the trash: {}", short_link))?;
+            settings
+                .reserved
+                .check(&link.short_link)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            let pending = chain::Pending {
+                short_link: &link.short_link,
+                url: &link.url,
+                replaces: None,
+            };
+            chain::check_pending(
+                &mut *pool.acquire().await?,
+                &settings.urls,
+                pending,
+                settings.max_chain_depth,
+            )
+            .await?
+            .map_err(|e| anyhow::anyhow!(e))?;
+            queries::restore_link(&pool, &short_link).await?;
+            println!("Restored {}", short_link);
+        }
+        TrashCommand::Purge {
+            short_link: Some(short_link),
+        } => {
+            if !queries::purge_link(&pool, &short_link).await? {
+                anyhow::bail!("Link not found in the trash: {}", short_link);
+            }
+            println!("Purged {}", short_link);
+        }
+        TrashCommand::Purge { short_link: None } => {
+            let days = settings.trash_retention_days;
+            if days == 0 {
+                anyhow::bail!("Trash retention is disabled; name the link to purge");
+            }
+            let purged = queries::purge_trash(&pool, days).await?;
+            println!(
+                "Purged {} link(s) deleted more than {} days ago",
+                purged, days
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn ls(db_path: &str, params: &SearchParams) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    // Ranked by the same index as the web search, typo candidates included.
+    let index = LinkIndex::new(pool.clone(), HitRecorder::spawn(pool));
+    let (links, total) = index.search(params).await?;
+    let width = links
+        .iter()
+        .map(|l| l.short_link.chars().count())
+        .max()
+        .unwrap_or(0);
+    for link in &links {
+        println!(
+            "{:width$}  {:>6}  {}",
+            link.short_link,
+            link.hits,
+            link.url,
+            width = width
+        );
+    }
+    if params.has_next(total) {
+        eprintln!(
+            "({} of {} links; more results: --page {})",
+            links.len(),
+            total,
+            params.page + 1
+        );
+    }
+    Ok(())
+}
+
+async fn show(db_path: &str, short_link: &str) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    let link = queries::fetch_link(&pool, short_link)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Link not found: {}", short_link))?;
+    println!("Short link:  {}", link.short_link);
+    println!("URL:         {}", link.url);
+    if !link.description.is_empty() {
+        println!("Description: {}", link.description);
+    }
+    if !link.tags.is_empty() {
+        println!("Tags:        {}", link.tags.join(", "));
+    }
+    if let Some(owner) = &link.owner {
+        println!("Owner:       {}", owner);
+    }
+    println!(
+        "Created:     {}",
+        link.created_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("Hits:        {}", link.hits);
+    match link.last_accessed {
+        Some(at) => println!("Last used:   {}", at.format("%Y-%m-%d %H:%M:%S")),
+        None => println!("Last used:   never"),
+    }
+    Ok(())
+}
+
+async fn migrate(db_path: &str) -> anyhow::Result<()> {
+    let pool = connect_db(db_path).await?;
+    let applied = migrations::migrate(&pool).await?;
+    if applied.is_empty() {
+        println!("Schema is up to date.");
+    }
+    for migration in &applied {
+        println!("Applied {:04} {}", migration.version, migration.name);
+    }
+    println!(
+        "Database schema version: {}",
+        migrations::current_version(&pool).await?
+    );
+    Ok(())
+}
+
+async fn export(
+    db_path: &str,
+    format: Option<Format>,
+    output: Option<String>,
+) -> anyhow::Result<()> {
+    let format = format
+        .or_else(|| output.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Json);
+    let pool = setup_db(db_path).await?;
+    let links = transfer::fetch_all(&pool).await?;
+    let data = transfer::export(&links, format)?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, data)?;
+            eprintln!("Exported {} links to {}", links.len(), path);
+        }
+        None => std::io::stdout().write_all(data.as_bytes())?,
+    }
+    Ok(())
+}
+
+async fn import(
+    db_path: &str,
+    file: &str,
+    format: Option<Format>,
+    policy: ConflictPolicy,
+    dry_run: bool,
+    settings: &Settings,
+) -> anyhow::Result<()> {
+    let format = format
+        .or_else(|| Format::from_path(file))
+        .ok_or_else(|| anyhow::anyhow!("Cannot infer format of '{}'; pass --format", file))?;
+    let data = if file == "-" {
+        let mut data = String::new();
+        std::io::stdin().read_to_string(&mut data)?;
+        data
+    } else {
+        std::fs::read_to_string(file)?
+    };
+    let pool = setup_db(db_path).await?;
+    let links = transfer::parse(&data, format)?;
+    let report = transfer::import(&pool, links, policy, dry_run, None, settings).await?;
+    print!("{}", report);
+    Ok(())
+}
+
+async fn render_config(
+    db_path: &str,
+    kind: ConfigKind,
+    mut ctx: RenderContext,
+    output: Option<String>,
+) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    for shortcut in queries::list_host_shortcuts(&pool).await? {
+        if !ctx.hostnames.contains(&shortcut.host) {
+            ctx.hostnames.push(shortcut.host);
+        }
+    }
+    let data = render::render(kind, &ctx);
+    match output {
+        Some(path) => std::fs::write(path, data)?,
+        None => std::io::stdout().write_all(data.as_bytes())?,
+    }
+    Ok(())
+}
+
+async fn host(db_path: &str, command: HostCommand, urls: &UrlPolicy) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    match command {
+        HostCommand::Add { host, url } => {
+            let mut shortcut = NewHostShortcut { host, url };
+            shortcut.validate().map_err(|e| anyhow::anyhow!(e))?;
+            shortcut.url = urls
+                .check_host_shortcut(&shortcut.host, &shortcut.url)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            queries::insert_host_shortcut(&pool, &shortcut, None)
+                .await
+                .map_err(|e| match e {
+                    sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                        anyhow::anyhow!("Host shortcut '{}' already exists", shortcut.host)
+                    }
+                    e => e.into(),
+                })?;
+            println!("Created {} -> {}", shortcut.host, shortcut.url);
+        }
+        HostCommand::Rm { host } => {
+            let host = normalize_host(&host);
+            if !queries::delete_host_shortcut(&pool, &host).await? {
+                anyhow::bail!("Host shortcut not found: {}", host);
+            }
+            println!("Deleted {}", host);
+        }
+        HostCommand::Ls => {
+            let hosts = queries::list_host_shortcuts(&pool).await?;
+            let width = hosts
+                .iter()
+                .map(|h| h.host.chars().count())
+                .max()
+                .unwrap_or(0);
+            for host in &hosts {
+                println!("{:width$}  {}", host.host, host.url, width = width);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn user(db_path: &str, command: UserCommand) -> anyhow::Result<()> {
+    let pool = setup_db(db_path).await?;
+    match command {
+        UserCommand::Add {
+            username,
+            password,
+            admin,
+        } => {
+            let password = match password {
+                Some(password) => password,
+                None => {
+                    let mut line = String::new();
+                    std::io::stdin().read_line(&mut line)?;
+                    line.trim_end_matches(['\r', '\n']).to_string()
+                }
+            };
+            auth::create_user(&pool, &username, &password, admin).await?;
+            println!("Created user {}", username);
+        }
+        UserCommand::Rm { username } => {
+            if !auth::delete_user(&pool, &username).await? {
+                anyhow::bail!("User not found: {}", username);
+            }
+            println!("Deleted user {}", username);
+        }
+        UserCommand::Ls => {
+            for user in auth::list_users(&pool).await? {
+                if user.is_admin {
+                    println!("{} (admin)", user.username);
+                } else {
+                    println!("{}", user.username);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn serve(db_path: &str, config: &Config) -> anyhow::Result<()> {
+    let pool = connect_db_with(db_path, config.pool_size).await?;
+    migrations::migrate(&pool).await?;
+    let settings = config.settings();
+    for short_link in settings.reserved.find_conflicts(&pool).await? {
+        let name = settings.reserved.conflict(&short_link).unwrap_or_default();
+        warn!(
+            "Short link '{}' can't be reached because /{} is reserved; rename it",
+            short_link, name
+        );
+    }
+    if let Err(e) = health::check_search_index(&pool).await {
+        warn!("{}", e);
+    }
+    let app_state = AppState::new(pool.clone()).with_settings(settings);
+    let shortcuts = app_state.hosts.clone();
+    let app = create_router(app_state);
+
+    if let Some(listen) = config.dns.listen {
+        let addresses = if config.dns.addresses.is_empty() {
+            vec![dns::detect_address().map_err(|e| {
+                anyhow::anyhow!(
+                    "Could not detect this host's address ({}); pass --dns-address",
+                    e
+                )
+            })?]
+        } else {
+            config.dns.addresses.clone()
+        };
+        let server = DnsServer::bind(DnsSettings {
+            listen,
+            hosts: config.dns.hosts.clone(),
+            addresses,
+            upstream: config.dns.upstream,
+        })
+        .await?
+        .with_host_shortcuts(shortcuts);
+        tokio::spawn(async move {
+            if let Err(e) = server.run().await {
+                tracing::error!("DNS responder stopped: {}", e);
+            }
+        });
+    }
+
+    let addr = SocketAddr::new(config.bind, config.port);
+    info!("Listening on {}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    match sd_notify::notify("READY=1") {
+        Ok(true) => {
+            if let Some(interval) = sd_notify::watchdog_interval() {
+                info!("Pinging the systemd watchdog every {:?}", interval / 2);
+                tokio::spawn(sd_notify::run_watchdog(pool.clone(), interval));
+            }
+        }
+        Ok(false) => {}
+        Err(e) => warn!("Failed to notify systemd: {}", e),
+    }
+    if config.trash_retention_days > 0 {
+        tokio::spawn(trash::run_purge(pool.clone(), config.trash_retention_days));
+    }
+    // Peer addresses decide whether the proxy auth header is believed.
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    auth::{AuthMode, AuthSettings},
+    chain,
+    reserved::ReservedNames,
+    state::Settings,
+    trash,
+    url_policy::{DEFAULT_SCHEMES, UrlPolicy},
+};
+
+/// Prefix of the environment variables that override the config file.
+pub const ENV_PREFIX: &str = "TO_LINKS_";
+const FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+/// The effective settings: built-in defaults, overridden by the config file,
+/// then by `TO_LINKS_*` environment variables, then by command line flags.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: IpAddr,
+    pub port: u16,
+    /// SQLite database; defaults to `~/.local/share/to-links/app.db`.
+    pub db: Option<String>,
+    pub pool_size: u32,
+    pub log_format: LogFormat,
+    pub auto_redirect_typos: bool,
+    /// Short link names to refuse besides the app's own routes.
+    pub reserved_names: Vec<String>,
+    /// URL schemes link targets may use.
+    pub allowed_schemes: Vec<String>,
+    /// Links followed server-side when a link points at another one.
+    pub max_chain_depth: usize,
+    /// Days a deleted link stays in the trash; 0 keeps it until purged by hand.
+    pub trash_retention_days: u32,
+    pub auth: AuthConfig,
+    pub dns: DnsConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+    pub header: String,
+    pub admins: Vec<String>,
+    /// Proxies besides loopback allowed to set `header`.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// The built-in DNS responder, enabled by setting `listen`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DnsConfig {
+    pub listen: Option<SocketAddr>,
+    pub hosts: Vec<String>,
+    pub addresses: Vec<IpAddr>,
+    pub upstream: Option<SocketAddr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 3000,
+            db: None,
+            pool_size: crate::DEFAULT_POOL_SIZE,
+            log_format: LogFormat::Text,
+            auto_redirect_typos: false,
+            reserved_names: Vec::new(),
+            allowed_schemes: DEFAULT_SCHEMES.iter().map(|s| s.to_string()).collect(),
+            max_chain_depth: chain::DEFAULT_MAX_DEPTH,
+            trash_retention_days: trash::DEFAULT_RETENTION_DAYS,
+            auth: AuthConfig::default(),
+            dns: DnsConfig::default(),
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        let defaults = AuthSettings::default();
+        Self {
+            mode: defaults.mode,
+            header: defaults.proxy_header,
+            admins: defaults.admins,
+            trusted_proxies: defaults.trusted_proxies,
+        }
+    }
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            hosts: vec!["to".to_string()],
+            addresses: Vec::new(),
+            upstream: None,
+        }
+    }
+}
+
+impl Config {
+    /// The config file to read: `explicit` if given, otherwise the first
+    /// existing file among `$XDG_CONFIG_HOME/to-links/config.toml` and
+    /// `~/.local/share/to-links/config.toml`.
+    pub fn locate(explicit: Option<&Path>) -> Option<PathBuf> {
+        if let Some(path) = explicit {
+            return Some(path.to_path_buf());
+        }
+        [
+            dirs::config_dir().map(|d| d.join("to-links").join(FILE_NAME)),
+            dirs::home_dir().map(|d| d.join(".local/share/to-links").join(FILE_NAME)),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|p| p.is_file())
+    }
+
+    /// Parses a config file on top of the defaults.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// Applies `TO_LINKS_*` variables from `vars`. Lists are comma separated.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<()> {
+        for (key, value) in vars {
+            let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let invalid = |e: String| anyhow::anyhow!("Invalid {}={:?}: {}", key, value, e);
+            match name {
+                "BIND" => self.bind = parse(&value).map_err(invalid)?,
+                "PORT" => self.port = parse(&value).map_err(invalid)?,
+                "DB" => self.db = Some(value.clone()),
+                "POOL_SIZE" => self.pool_size = parse(&value).map_err(invalid)?,
+                "LOG_FORMAT" => self.log_format = parse_enum(&value).map_err(invalid)?,
+                "AUTO_REDIRECT_TYPOS" => {
+                    self.auto_redirect_typos = parse(&value).map_err(invalid)?
+                }
+                "RESERVED_NAMES" => self.reserved_names = parse_list(&value).map_err(invalid)?,
+                "ALLOWED_SCHEMES" => self.allowed_schemes = parse_list(&value).map_err(invalid)?,
+                "MAX_CHAIN_DEPTH" => self.max_chain_depth = parse(&value).map_err(invalid)?,
+                "TRASH_RETENTION_DAYS" => {
+                    self.trash_retention_days = parse(&value).map_err(invalid)?
+                }
+                "AUTH_MODE" => self.auth.mode = parse_enum(&value).map_err(invalid)?,
+                "AUTH_HEADER" => self.auth.header = value.clone(),
+                "AUTH_ADMINS" => self.auth.admins = parse_list(&value).map_err(invalid)?,
+                "AUTH_TRUSTED_PROXIES" => {
+                    self.auth.trusted_proxies = parse_list(&value).map_err(invalid)?
+                }
+                "DNS_LISTEN" => self.dns.listen = Some(parse(&value).map_err(invalid)?),
+                "DNS_HOSTS" => self.dns.hosts = parse_list(&value).map_err(invalid)?,
+                "DNS_ADDRESSES" => self.dns.addresses = parse_list(&value).map_err(invalid)?,
+                "DNS_UPSTREAM" => self.dns.upstream = Some(parse(&value).map_err(invalid)?),
+                // TO_LINKS_CONFIG picks the file itself.
+                "CONFIG" => {}
+                _ => anyhow::bail!("Unknown setting {}", key),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn auth_settings(&self) -> AuthSettings {
+        AuthSettings {
+            mode: self.auth.mode,
+            proxy_header: self.auth.header.clone(),
+            admins: self.auth.admins.clone(),
+            trusted_proxies: self.auth.trusted_proxies.clone(),
+        }
+    }
+
+    /// The request handling settings, shared by the server and the commands
+    /// that change links.
+    pub fn settings(&self) -> Settings {
+        Settings {
+            auto_redirect_typos: self.auto_redirect_typos,
+            auth: self.auth_settings(),
+            reserved: ReservedNames::from_routes().with_extras(&self.reserved_names),
+            urls: UrlPolicy::new(&self.allowed_schemes, &self.dns.hosts),
+            max_chain_depth: self.max_chain_depth,
+            trash_retention_days: self.trash_retention_days,
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("config is always serializable")
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.trim().parse().map_err(|e: T::Err| e.to_string())
+}
+
+fn parse_enum<T: clap::ValueEnum>(value: &str) -> Result<T, String> {
+    T::from_str(value.trim(), true)
+}
+
+fn parse_list<T: FromStr>(value: &str) -> Result<Vec<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_file_overrides_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            port = 8080
+            log_format = "json"
+
+            [dns]
+            listen = "0.0.0.0:53"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.dns.listen, Some("0.0.0.0:53".parse().unwrap()));
+        // Untouched values keep their defaults
+        assert_eq!(config.pool_size, crate::DEFAULT_POOL_SIZE);
+        assert_eq!(config.dns.hosts, vec!["to"]);
+
+        assert!(toml::from_str::<Config>("prot = 1").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = Config::default();
+        config
+            .apply_env(vars(&[
+                ("TO_LINKS_PORT", "4000"),
+                ("TO_LINKS_AUTH_MODE", "Proxy"),
+                ("TO_LINKS_DNS_HOSTS", "to, maps"),
+                ("TO_LINKS_AUTO_REDIRECT_TYPOS", "true"),
+                ("HOME", "/root"),
+            ]))
+            .unwrap();
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.auth.mode, AuthMode::Proxy);
+        assert_eq!(config.dns.hosts, vec!["to", "maps"]);
+        assert!(config.auto_redirect_typos);
+
+        assert!(config.apply_env(vars(&[("TO_LINKS_PORT", "x")])).is_err());
+        assert!(config.apply_env(vars(&[("TO_LINKS_PROT", "1")])).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config {
+            db: Some("/srv/to-links.db".to_string()),
+            ..Default::default()
+        };
+        config.dns.upstream = Some("1.1.1.1:53".parse().unwrap());
+        let parsed: Config = toml::from_str(&config.to_toml()).unwrap();
+        assert_eq!(parsed, config);
+    }
+}
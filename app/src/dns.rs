@@ -0,0 +1,329 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::net::UdpSocket;
+use tracing::{debug, error, info, warn};
+
+use crate::hosts::{HostShortcuts, normalize_host};
+
+const TTL: u32 = 300;
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_PACKET: usize = 4096;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_REFUSED: u8 = 5;
+
+#[derive(Debug, Clone)]
+pub struct DnsSettings {
+    pub listen: SocketAddr,
+    /// Hostnames answered locally, without the `.lan` suffix.
+    pub hosts: Vec<String>,
+    /// Addresses returned for those hostnames.
+    pub addresses: Vec<IpAddr>,
+    /// Resolver for every other name; such queries are refused when unset.
+    pub upstream: Option<SocketAddr>,
+}
+
+/// A parsed DNS query with a single question.
+#[derive(Debug, PartialEq)]
+struct Query<'a> {
+    id: u16,
+    flags: u16,
+    /// Lowercased name without the trailing dot.
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    /// The raw question section, echoed back in the response.
+    question: &'a [u8],
+}
+
+fn parse_query(packet: &[u8]) -> Result<Query<'_>, &'static str> {
+    if packet.len() < 12 {
+        return Err("packet too short");
+    }
+    let id = u16::from_be_bytes([packet[0], packet[1]]);
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    if flags & 0x8000 != 0 {
+        return Err("not a query");
+    }
+    if qdcount != 1 {
+        return Err("expected exactly one question");
+    }
+
+    let mut labels = Vec::new();
+    let mut pos = 12;
+    loop {
+        let len = *packet.get(pos).ok_or("truncated name")? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        if len > 63 {
+            return Err("compressed or invalid label");
+        }
+        let label = packet.get(pos..pos + len).ok_or("truncated name")?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        pos += len;
+    }
+    let fixed = packet.get(pos..pos + 4).ok_or("truncated question")?;
+    Ok(Query {
+        id,
+        flags,
+        name: labels.join("."),
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+        question: &packet[12..pos + 4],
+    })
+}
+
+/// Builds a response to `query` with the given response code and answers.
+fn build_response(query: &Query, rcode: u8, answers: &[IpAddr]) -> Vec<u8> {
+    let opcode = query.flags & 0x7800;
+    let rd = query.flags & 0x0100;
+    // QR, the RD bit copied, and AA when answering for a shortcut name.
+    let aa = if rcode == 0 { 0x0400 } else { 0 };
+    let flags = 0x8000 | opcode | aa | rd | rcode as u16;
+
+    let mut out = Vec::with_capacity(12 + query.question.len() + answers.len() * 28);
+    out.extend_from_slice(&query.id.to_be_bytes());
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(query.question);
+    for address in answers {
+        // Pointer to the name in the question section.
+        out.extend_from_slice(&0xC00Cu16.to_be_bytes());
+        let (rtype, rdata) = match address {
+            IpAddr::V4(v4) => (TYPE_A, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (TYPE_AAAA, v6.octets().to_vec()),
+        };
+        out.extend_from_slice(&rtype.to_be_bytes());
+        out.extend_from_slice(&CLASS_IN.to_be_bytes());
+        out.extend_from_slice(&TTL.to_be_bytes());
+        out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        out.extend_from_slice(&rdata);
+    }
+    out
+}
+
+/// Builds an error response from the header alone, for unparseable queries.
+fn error_response(packet: &[u8], rcode: u8) -> Option<Vec<u8>> {
+    if packet.len() < 12 || packet[2] & 0x80 != 0 {
+        return None;
+    }
+    let mut out = packet[..12].to_vec();
+    out[2] = 0x80 | (packet[2] & 0x79);
+    out[3] = rcode;
+    out[4..12].fill(0);
+    Some(out)
+}
+
+/// Whether `name` is one of `hosts`, optionally followed by `.lan`.
+fn is_local(hosts: &[String], name: &str) -> bool {
+    hosts.contains(&normalize_host(name))
+}
+
+/// Returns the address this host uses to reach the network, for answering
+/// when no address was configured. No packet is sent.
+pub fn detect_address() -> std::io::Result<IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("192.0.2.1:53")?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// A minimal DNS responder for the shortcut hostnames.
+///
+/// It answers `A`/`AAAA` queries for the configured names (and their `.lan`
+/// variants) with this host's addresses, and forwards every other query to an
+/// upstream resolver, or refuses it when none is configured.
+pub struct DnsServer {
+    socket: Arc<UdpSocket>,
+    settings: Arc<DnsSettings>,
+    /// Host shortcuts answered as well.
+    shortcuts: Option<HostShortcuts>,
+}
+
+impl DnsServer {
+    pub async fn bind(mut settings: DnsSettings) -> std::io::Result<Self> {
+        for host in &mut settings.hosts {
+            *host = normalize_host(host);
+        }
+        let socket = UdpSocket::bind(settings.listen).await?;
+        Ok(Self {
+            socket: Arc::new(socket),
+            settings: Arc::new(settings),
+            shortcuts: None,
+        })
+    }
+
+    /// Also answers for every host shortcut in `shortcuts`.
+    pub fn with_host_shortcuts(mut self, shortcuts: HostShortcuts) -> Self {
+        self.shortcuts = Some(shortcuts);
+        self
+    }
+
+    async fn is_local(&self, name: &str) -> bool {
+        if is_local(&self.settings.hosts, name) {
+            return true;
+        }
+        let Some(shortcuts) = &self.shortcuts else {
+            return false;
+        };
+        match shortcuts.get(&normalize_host(name)).await {
+            Ok(shortcut) => shortcut.is_some(),
+            Err(e) => {
+                error!("Host shortcut lookup failed: {:?}", e);
+                false
+            }
+        }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Serves queries until the socket fails.
+    pub async fn run(self) -> std::io::Result<()> {
+        info!(
+            "DNS responder listening on {} for {}",
+            self.local_addr()?,
+            self.settings.hosts.join(", ")
+        );
+        let mut buf = vec![0u8; MAX_PACKET];
+        loop {
+            let (len, peer) = self.socket.recv_from(&mut buf).await?;
+            let packet = &buf[..len];
+            let query = match parse_query(packet) {
+                Ok(query) => query,
+                Err(e) => {
+                    debug!("Malformed DNS query from {}: {}", peer, e);
+                    if let Some(response) = error_response(packet, RCODE_FORMERR) {
+                        self.reply(&response, peer).await;
+                    }
+                    continue;
+                }
+            };
+
+            if query.qclass == CLASS_IN && self.is_local(&query.name).await {
+                let answers: Vec<IpAddr> = self
+                    .settings
+                    .addresses
+                    .iter()
+                    .copied()
+                    .filter(|a| match query.qtype {
+                        TYPE_A => a.is_ipv4(),
+                        TYPE_AAAA => a.is_ipv6(),
+                        _ => false,
+                    })
+                    .collect();
+                // A name we own with no address of the asked type is NODATA.
+                self.reply(&build_response(&query, 0, &answers), peer).await;
+            } else if let Some(upstream) = self.settings.upstream {
+                let socket = self.socket.clone();
+                let packet = packet.to_vec();
+                tokio::spawn(async move {
+                    match forward(&packet, upstream).await {
+                        Ok(response) => {
+                            if let Err(e) = socket.send_to(&response, peer).await {
+                                warn!("Failed to send DNS response to {}: {}", peer, e);
+                            }
+                        }
+                        Err(e) => warn!("DNS upstream {} failed: {}", upstream, e),
+                    }
+                });
+            } else {
+                self.reply(&build_response(&query, RCODE_REFUSED, &[]), peer)
+                    .await;
+            }
+        }
+    }
+
+    async fn reply(&self, response: &[u8], peer: SocketAddr) {
+        if let Err(e) = self.socket.send_to(response, peer).await {
+            error!("Failed to send DNS response to {}: {}", peer, e);
+        }
+    }
+}
+
+/// Relays a raw query to `upstream` and returns its raw response.
+async fn forward(packet: &[u8], upstream: SocketAddr) -> std::io::Result<Vec<u8>> {
+    let bind: SocketAddr = if upstream.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(upstream).await?;
+    socket.send(packet).await?;
+    let mut buf = vec![0u8; MAX_PACKET];
+    let len = tokio::time::timeout(UPSTREAM_TIMEOUT, socket.recv(&mut buf))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "upstream timed out"))??;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_packet(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let packet = query_packet("To.LAN", TYPE_AAAA);
+        let query = parse_query(&packet).unwrap();
+        assert_eq!(query.id, 0x1234);
+        assert_eq!(query.name, "to.lan");
+        assert_eq!(query.qtype, TYPE_AAAA);
+        assert_eq!(query.question, &packet[12..]);
+
+        assert!(parse_query(&packet[..10]).is_err());
+        assert!(parse_query(&packet[..packet.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_local_names() {
+        let hosts = vec!["to".to_string(), "maps".to_string()];
+        assert!(is_local(&hosts, "to"));
+        assert!(is_local(&hosts, "maps.lan"));
+        assert!(!is_local(&hosts, "lan"));
+        assert!(!is_local(&hosts, "to.example.com"));
+        assert!(!is_local(&hosts, "tolan"));
+    }
+
+    #[test]
+    fn test_build_response() {
+        let packet = query_packet("to", TYPE_A);
+        let query = parse_query(&packet).unwrap();
+        let response = build_response(&query, 0, &["192.168.1.2".parse().unwrap()]);
+        assert_eq!(&response[..2], &[0x12, 0x34]);
+        // QR, AA and RD set, NOERROR
+        assert_eq!(&response[2..4], &[0x85, 0x00]);
+        assert_eq!(&response[6..8], &[0, 1]);
+        assert_eq!(&response[response.len() - 4..], &[192, 168, 1, 2]);
+
+        let refused = build_response(&query, RCODE_REFUSED, &[]);
+        assert_eq!(refused[3] & 0x0F, RCODE_REFUSED);
+        assert_eq!(refused.len(), packet.len());
+    }
+}
@@ -8,6 +8,14 @@ use tracing::error;
 
 pub struct AppError(pub StatusCode, pub anyhow::Error);
 
+/// The original status and message of an [`AppError`], attached to its
+/// response so that [`crate::api::json_errors`] can re-render it as JSON.
+#[derive(Clone, Debug)]
+pub struct ErrorInfo {
+    pub status: StatusCode,
+    pub message: String,
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         error!("Error: {:?}", self.1);
@@ -22,6 +30,10 @@ impl IntoResponse for AppError {
             self.0
         };
         let mut res = (status, HtmlTemplate(template)).into_response();
+        res.extensions_mut().insert(ErrorInfo {
+            status: self.0,
+            message: message.clone(),
+        });
         res.headers_mut()
             .insert("HX-Retarget", "#form-error".parse().unwrap());
         res.headers_mut()
@@ -1,166 +1,438 @@
+use askama::Template;
 use axum::{
-    Form,
+    Form, Json,
     extract::{Path as AxumPath, Query, State},
-    http::{HeaderMap, StatusCode},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Redirect, Response},
 };
+use serde::Deserialize;
+use std::time::Instant;
 use tracing::error;
 
 use crate::{
+    api::{Payload, ResponseFormat, wants_json},
+    auth::{self, AuthMode, Viewer},
+    chain::{self, Chain, ChainEnd},
     error::{AppError, HtmlTemplate},
-    models::{Link, NewLink, SearchParams},
+    metrics::RedirectOutcome,
+    models::{
+        Link, LinkUpdate, LinksPage, NewHostShortcut, NewLink, SearchParams, SortOrder, TrashedLink,
+    },
+    queries,
     state::AppState,
-    templates::{CreateLinkTemplate, IndexTemplate, LinkRowTemplate, LinksListTemplate},
-    utils::levenshtein,
+    templates::{
+        CreateLinkTemplate, HostRowTemplate, HostsListTemplate, IndexTemplate, LinkDetailTemplate,
+        LinkEditRowTemplate, LinkRowTemplate, LinksListTemplate, LoginTemplate, OpenSearchTemplate,
+        RedirectErrorTemplate, ToastTemplate, TrashListTemplate,
+    },
+    transfer::{self, ExportParams, ImportParams},
 };
 
-pub async fn show_ui() -> impl IntoResponse {
-    HtmlTemplate(IndexTemplate)
+pub async fn show_ui(viewer: Viewer) -> impl IntoResponse {
+    HtmlTemplate(IndexTemplate {
+        username: viewer.username().map(str::to_string),
+        local_auth: viewer.mode == AuthMode::Local,
+        show_trash: viewer.require_user().is_ok(),
+        show_export: viewer.require_admin().is_ok(),
+        q: String::new(),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+}
+
+pub async fn show_login() -> impl IntoResponse {
+    HtmlTemplate(LoginTemplate { error: None })
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    Form(form): Form<LoginForm>,
+) -> Result<Response, AppError> {
+    let token = auth::login(&state.pool, &form.username, &form.password)
+        .await
+        .map_err(|e| {
+            error!("Login error: {:?}", e);
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to sign in"),
+            )
+        })?;
+    match token {
+        Some(token) => Ok((
+            [(header::SET_COOKIE, auth::session_cookie(&token))],
+            Redirect::to("/link"),
+        )
+            .into_response()),
+        None => Ok((
+            StatusCode::UNAUTHORIZED,
+            HtmlTemplate(LoginTemplate {
+                error: Some("Invalid username or password"),
+            }),
+        )
+            .into_response()),
+    }
+}
+
+pub async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(token) = auth::cookie(&headers, auth::SESSION_COOKIE)
+        && let Err(e) = auth::logout(&state.pool, token).await
+    {
+        error!("Logout error: {:?}", e);
+    }
+    (
+        [(header::SET_COOKIE, auth::clear_session_cookie())],
+        Redirect::to("/link"),
+    )
+        .into_response()
 }
 
 pub async fn redirect_link(
     State(state): State<AppState>,
-    AxumPath(short_link): AxumPath<String>,
+    headers: HeaderMap,
+    AxumPath(params): AxumPath<Vec<(String, String)>>,
 ) -> Result<Response, AppError> {
-    let link: Option<Link> =
-        sqlx::query_as("SELECT short_link, url, created_at FROM links WHERE short_link = ?")
-            .bind(&short_link)
-            .fetch_optional(&state.pool)
+    let path = params
+        .into_iter()
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>()
+        .join("/");
+    let failed = |_: &AppError| state.metrics.record_redirect(RedirectOutcome::Error);
+    if let Some(chain) = follow_chain(&state, &path, None)
+        .await
+        .inspect_err(failed)?
+    {
+        return redirect_chain(&state, &headers, chain).await;
+    }
+
+    if state.settings.auto_redirect_typos {
+        let close = state
+            .index
+            .similar(&path, 1)
             .await
-            .map_err(|_| {
-                AppError(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    anyhow::anyhow!("Database error"),
-                )
-            })?;
+            .map_err(chain_error)
+            .inspect_err(failed)?;
+        if let [only] = close.as_slice()
+            && let Some(chain) = follow_chain(&state, only, None).await.inspect_err(failed)?
+        {
+            return redirect_chain(&state, &headers, chain).await;
+        }
+    }
 
-    match link {
-        Some(l) => Ok(Redirect::to(&l.url).into_response()),
-        None => Ok(HtmlTemplate(CreateLinkTemplate { short_link }).into_response()),
+    state.metrics.record_redirect(RedirectOutcome::Miss);
+    let suggestions = suggest_links(&state, &path).await;
+    Ok(HtmlTemplate(CreateLinkTemplate {
+        short_link: path,
+        suggestions,
+    })
+    .into_response())
+}
+
+fn chain_error(e: sqlx::Error) -> AppError {
+    error!("Chain resolution error: {:?}", e);
+    AppError(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        anyhow::anyhow!("Database error"),
+    )
+}
+
+/// Resolves `path` through any chain of links pointing at other links,
+/// using the in-memory index.
+async fn follow_chain(
+    state: &AppState,
+    path: &str,
+    pending: Option<chain::Pending<'_>>,
+) -> Result<Option<Chain>, AppError> {
+    chain::follow(
+        &mut state.index.clone(),
+        &state.settings.urls,
+        path,
+        state.settings.max_chain_depth,
+        pending,
+    )
+    .await
+    .map_err(chain_error)
+}
+
+/// Redirects to the end of `chain`, counting a hit for every link on it.
+///
+/// A chain that loops or is too long is answered with 508: as a full page
+/// when a browser followed the link, as an error for HTMX and JSON clients.
+async fn redirect_chain(
+    state: &AppState,
+    headers: &HeaderMap,
+    chain: Chain,
+) -> Result<Response, AppError> {
+    for hop in &chain.hops {
+        state.index.record_hit(&hop.short_link).await;
+    }
+    state.metrics.record_redirect(match chain.end {
+        ChainEnd::Resolved { .. } => RedirectOutcome::Hit,
+        ChainEnd::Loop | ChainEnd::TooLong => RedirectOutcome::Loop,
+    });
+    let message = match chain.end {
+        ChainEnd::Resolved { url } => return Ok(Redirect::to(&url).into_response()),
+        ChainEnd::Loop => format!("Redirect loop: {}", chain.describe()),
+        ChainEnd::TooLong => format!(
+            "Redirect chain is longer than {} links: {}",
+            state.settings.max_chain_depth,
+            chain.describe()
+        ),
+    };
+    if wants_json(headers) || headers.contains_key("hx-request") {
+        return Err(AppError(
+            StatusCode::LOOP_DETECTED,
+            anyhow::anyhow!(message),
+        ));
+    }
+    let page = RedirectErrorTemplate {
+        short_link: chain.hops[0].short_link.clone(),
+        message,
+    };
+    Ok((StatusCode::LOOP_DETECTED, HtmlTemplate(page)).into_response())
+}
+
+/// Refuses to save a link whose chain would loop or grow too long.
+async fn check_chain(state: &AppState, pending: chain::Pending<'_>) -> Result<(), AppError> {
+    let mut conn = state.pool.acquire().await.map_err(chain_error)?;
+    chain::check_pending(
+        &mut *conn,
+        &state.settings.urls,
+        pending,
+        state.settings.max_chain_depth,
+    )
+    .await
+    .map_err(chain_error)?
+    .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))
+}
+
+/// Number of "did you mean" links shown on the missing-link page.
+const SUGGESTION_LIMIT: u32 = 5;
+
+/// Finds existing links whose names are close to `short_link`, using the same
+/// ranking as the dashboard search.
+async fn suggest_links(state: &AppState, short_link: &str) -> Vec<Link> {
+    let params = SearchParams {
+        q: Some(short_link.to_string()),
+        page: 1,
+        limit: SUGGESTION_LIMIT,
+        sort: SortOrder::Recent,
+    };
+    match state.index.search(&params).await {
+        Ok((links, _)) => links,
+        Err(e) => {
+            error!("Suggestion error: {:?}", e);
+            Vec::new()
+        }
     }
 }
 
 pub async fn list_links(
     State(state): State<AppState>,
+    format: ResponseFormat,
     Query(params): Query<SearchParams>,
-) -> Result<impl IntoResponse, AppError> {
-    let limit = params.limit;
-    let offset = (params.page - 1) * limit;
-    let q = params.q.clone().unwrap_or_default();
-    let q_trim = q.trim();
-
-    let links = if !q_trim.is_empty() {
-        // Advanced Fuzzy Search
-        // 1. If short (<=3 chars), use standard prefix/trigram match
-        // 2. If long, break into trigrams and OR them to find candidates
-
-        let query_str = if q_trim.chars().count() <= 3 {
-            format!("{}", q_trim)
-        } else {
-            // Generate trigrams: "kubernetes" -> "kub" OR "ube" OR "ber" ...
-            let chars: Vec<char> = q_trim.chars().collect();
-            let mut trigrams = Vec::new();
-            for i in 0..chars.len().saturating_sub(2) {
-                let trigram: String = chars[i..i + 3].iter().collect();
-                // Escape double quotes just in case
-                let safe_tri = trigram.replace("\"", "\"\"");
-                trigrams.push(format!("\"{}\"", safe_tri));
-            }
-            if trigrams.is_empty() {
-                format!("{}", q_trim)
-            } else {
-                trigrams.join(" OR ")
-            }
-        };
+) -> Result<Response, AppError> {
+    let start = Instant::now();
+    let (links, total) = state.index.search(&params).await.map_err(|e| {
+        error!("Search error: {:?}", e);
+        AppError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow::anyhow!("Failed to fetch links"),
+        )
+    })?;
+    state.metrics.observe_search(start.elapsed());
+    let has_next = params.has_next(total);
+
+    if format == ResponseFormat::Json {
+        return Ok(Json(LinksPage {
+            links,
+            page: params.page,
+            limit: params.limit,
+            total,
+            has_next,
+            q: params.q.unwrap_or_default(),
+            sort: params.sort,
+        })
+        .into_response());
+    }
+
+    Ok(HtmlTemplate(LinksListTemplate {
+        links,
+        page: params.page,
+        pages: total.div_ceil(u64::from(params.limit.max(1))),
+        total,
+        has_next,
+        q: params.q.unwrap_or_default(),
+        sort: params.sort,
+    })
+    .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct SearchBox {
+    #[serde(default)]
+    pub q: String,
+}
+
+/// Browser search: `/search?q=wiki` goes straight to the `wiki` link, like
+/// `/wiki` would; any other query opens the dashboard with its results.
+pub async fn search(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    viewer: Viewer,
+    Query(search): Query<SearchBox>,
+) -> Result<Response, AppError> {
+    let q = search.q.trim();
+    if !q.is_empty()
+        && !q.contains(char::is_whitespace)
+        && let Some(chain) = follow_chain(&state, q.trim_matches('/'), None).await?
+    {
+        return redirect_chain(&state, &headers, chain).await;
+    }
+    Ok(HtmlTemplate(IndexTemplate {
+        username: viewer.username().map(str::to_string),
+        local_auth: viewer.mode == AuthMode::Local,
+        show_trash: viewer.require_user().is_ok(),
+        show_export: viewer.require_admin().is_ok(),
+        q: q.to_string(),
+    })
+    .into_response())
+}
 
-        // Fetch MORE candidates than the limit to allow re-sorting
-        // We'll fetch 4x the limit to have a good pool of candidates
-        let candidate_limit = limit * 4;
-
-        let mut candidates = sqlx::query_as::<_, Link>(
-            "SELECT l.short_link, l.url, l.created_at 
-             FROM links l
-             JOIN links_fts f ON l.rowid = f.rowid
-             WHERE links_fts MATCH ? 
-             ORDER BY rank
-             LIMIT ?",
+/// Number of completions offered to the browser's address bar.
+const COMPLETION_LIMIT: u32 = 10;
+
+/// The app's address as the client reached it, e.g. `http://to`, for
+/// documents that need absolute URLs.
+fn base_url(headers: &HeaderMap) -> String {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    format!(
+        "{}://{}",
+        header("x-forwarded-proto").unwrap_or("http"),
+        header(header::HOST.as_str()).unwrap_or("localhost")
+    )
+}
+
+/// The OpenSearch description that lets browsers install the app as a
+/// search engine.
+pub async fn opensearch_description(headers: HeaderMap) -> Result<Response, AppError> {
+    let xml = OpenSearchTemplate {
+        base_url: base_url(&headers),
+    }
+    .render()
+    .map_err(|e| {
+        error!("OpenSearch description error: {:?}", e);
+        AppError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow::anyhow!("Failed to render template"),
         )
-        .bind(query_str)
-        .bind(candidate_limit)
-        .fetch_all(&state.pool)
-        .await
-        .map_err(|e| {
-            error!("Search error: {:?}", e);
-            AppError(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow::anyhow!("Failed to fetch links"),
-            )
-        })?;
+    })?;
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            "application/opensearchdescription+xml",
+        )],
+        xml,
+    )
+        .into_response())
+}
 
-        // Refine sorting with Levenshtein in memory
-        candidates.sort_by(|a, b| {
-            let dist_a = levenshtein(&a.short_link, q_trim);
-            let dist_b = levenshtein(&b.short_link, q_trim);
-            dist_a.cmp(&dist_b)
-        });
-
-        // Apply pagination in memory since we re-sorted
-        // (Note: this simple pagination approach resets scope to the fetched candidates)
-        // For true deep pagination with sorting, we'd need to fetch all candidates,
-        // but for a fuzzy search, usually only the top results matter.
-        let start = offset as usize;
-        let end = std::cmp::min(start + limit as usize + 1, candidates.len());
-
-        if start >= candidates.len() {
-            Vec::new()
-        } else {
-            candidates[start..end].to_vec()
-        }
+/// Address bar completions in the OpenSearch suggestions format:
+/// `[query, [names], [descriptions], [urls]]`, ranked like the dashboard
+/// search.
+pub async fn opensearch_suggestions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(search): Query<SearchBox>,
+) -> Result<Response, AppError> {
+    let links = if search.q.trim().is_empty() {
+        Vec::new()
     } else {
-        sqlx::query_as::<_, Link>(
-            "SELECT short_link, url, created_at FROM links ORDER BY created_at DESC LIMIT ? OFFSET ?",
-        )
-        .bind(limit + 1) // Fetch one extra to check for next page
-        .bind(offset)
-        .fetch_all(&state.pool)
-        .await
-        .map_err(|e| {
-            error!("Search error: {:?}", e);
+        let params = SearchParams {
+            q: Some(search.q.clone()),
+            page: 1,
+            limit: COMPLETION_LIMIT,
+            sort: SortOrder::Recent,
+        };
+        let start = Instant::now();
+        let (links, _) = state.index.search(&params).await.map_err(|e| {
+            error!("Suggestion error: {:?}", e);
             AppError(
                 StatusCode::INTERNAL_SERVER_ERROR,
                 anyhow::anyhow!("Failed to fetch links"),
             )
-        })?
+        })?;
+        state.metrics.observe_search(start.elapsed());
+        links
     };
 
-    let has_next = links.len() > limit as usize;
-    let mut links = links; // make mutable
-    if has_next {
-        links.pop();
-    }
+    let base_url = base_url(&headers);
+    let names: Vec<&str> = links.iter().map(|l| l.short_link.as_str()).collect();
+    let descriptions: Vec<&str> = links
+        .iter()
+        .map(|l| {
+            if l.description.is_empty() {
+                l.url.as_str()
+            } else {
+                l.description.as_str()
+            }
+        })
+        .collect();
+    let urls: Vec<String> = links
+        .iter()
+        .map(|l| format!("{}/{}", base_url, l.short_link))
+        .collect();
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-suggestions+json")],
+        Json(serde_json::json!([search.q, names, descriptions, urls])),
+    )
+        .into_response())
+}
 
-    Ok(HtmlTemplate(LinksListTemplate {
-        links,
-        page: params.page,
-        has_next,
-        q,
-    }))
+fn check_url(state: &AppState, short_link: &str, url: &str) -> Result<String, AppError> {
+    state
+        .settings
+        .urls
+        .check_link(short_link, url)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))
+}
+
+fn check_reserved(state: &AppState, short_link: &str) -> Result<(), AppError> {
+    state
+        .settings
+        .reserved
+        .check(short_link)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))
 }
 
 pub async fn add_link(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Form(new_link): Form<NewLink>,
+    viewer: Viewer,
+    format: ResponseFormat,
+    Payload(mut new_link): Payload<NewLink>,
 ) -> Result<Response, AppError> {
+    viewer.require_user()?;
     // Basic validation for short_link
     if let Err(e) = new_link.validate() {
         return Err(AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)));
     }
+    check_reserved(&state, &new_link.short_link)?;
+    new_link.url = check_url(&state, &new_link.short_link, &new_link.url)?;
+    check_chain(
+        &state,
+        chain::Pending {
+            short_link: &new_link.short_link,
+            url: &new_link.url,
+            replaces: None,
+        },
+    )
+    .await?;
 
-    sqlx::query("INSERT INTO links (short_link, url) VALUES (?, ?)")
-        .bind(&new_link.short_link)
-        .bind(&new_link.url)
-        .execute(&state.pool)
+    queries::insert_link(&state.pool, &new_link, viewer.username())
         .await
         .map_err(|e| match e {
             sqlx::Error::Database(db_err) if db_err.is_unique_violation() => AppError(
@@ -172,19 +444,13 @@ pub async fn add_link(
                 anyhow::anyhow!("Failed to create link"),
             ),
         })?;
+    state.index.catch_up().await;
 
-    if headers.contains_key("hx-request") {
-        let link: Link =
-            sqlx::query_as("SELECT short_link, url, created_at FROM links WHERE short_link = ?")
-                .bind(&new_link.short_link)
-                .fetch_one(&state.pool)
-                .await
-                .map_err(|_| {
-                    AppError(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        anyhow::anyhow!("Failed to fetch created link"),
-                    )
-                })?;
+    if format == ResponseFormat::Json {
+        let link = fetch_link(&state, &new_link.short_link).await?;
+        Ok((StatusCode::CREATED, Json(link)).into_response())
+    } else if headers.contains_key("hx-request") {
+        let link = fetch_link(&state, &new_link.short_link).await?;
         Ok(HtmlTemplate(LinkRowTemplate { link }).into_response())
     } else {
         Ok(Redirect::to("/link").into_response())
@@ -193,11 +459,12 @@ pub async fn add_link(
 
 pub async fn delete_link(
     State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
     AxumPath(short_link): AxumPath<String>,
-) -> Result<StatusCode, AppError> {
-    let result = sqlx::query("DELETE FROM links WHERE short_link = ?")
-        .bind(short_link)
-        .execute(&state.pool)
+) -> Result<Response, AppError> {
+    viewer.require_owner(&fetch_link(&state, &short_link).await?)?;
+    let deleted = queries::delete_link(&state.pool, &short_link)
         .await
         .map_err(|_| {
             AppError(
@@ -206,12 +473,437 @@ pub async fn delete_link(
             )
         })?;
 
-    if result.rows_affected() == 0 {
+    if !deleted {
         return Err(AppError(
             StatusCode::NOT_FOUND,
             anyhow::anyhow!("Link not found"),
         ));
     }
+    state.index.catch_up().await;
 
+    if format == ResponseFormat::Json {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    // The row is swapped out; the toast offers to undo.
+    Ok((
+        [(HX_TRIGGER, TRASH_CHANGED)],
+        HtmlTemplate(ToastTemplate {
+            message: "Deleted",
+            short_link: &short_link,
+            undo: true,
+        }),
+    )
+        .into_response())
+}
+
+/// Tells the dashboard's trash list to reload.
+const TRASH_CHANGED: &str = "trash-changed";
+/// Tells the dashboard's trash and link lists to reload.
+const LINK_RESTORED: &str = "trash-changed, link-restored";
+const HX_TRIGGER: &str = "HX-Trigger";
+
+async fn fetch_trashed_link(state: &AppState, short_link: &str) -> Result<TrashedLink, AppError> {
+    queries::fetch_trashed_link(&state.pool, short_link)
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to fetch link"),
+            )
+        })?
+        .ok_or_else(|| {
+            AppError(
+                StatusCode::NOT_FOUND,
+                anyhow::anyhow!("Link not found in the trash"),
+            )
+        })
+}
+
+/// The trashed links the viewer could restore or purge.
+pub async fn list_trash(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
+) -> Result<Response, AppError> {
+    viewer.require_user()?;
+    let mut links = queries::list_trash(&state.pool).await.map_err(|e| {
+        error!("Trash list error: {:?}", e);
+        AppError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow::anyhow!("Failed to fetch the trash"),
+        )
+    })?;
+    links.retain(|link| viewer.may_change(link.owner.as_deref()));
+    match format {
+        ResponseFormat::Json => Ok(Json(links).into_response()),
+        ResponseFormat::Html => Ok(HtmlTemplate(TrashListTemplate {
+            links,
+            retention_days: state.settings.trash_retention_days,
+        })
+        .into_response()),
+    }
+}
+
+/// Takes a link out of the trash, as long as its name and target are still
+/// acceptable.
+pub async fn restore_link(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
+    AxumPath(short_link): AxumPath<String>,
+) -> Result<Response, AppError> {
+    let trashed = fetch_trashed_link(&state, &short_link).await?;
+    viewer.require_owner_of(&trashed.short_link, trashed.owner.as_deref())?;
+    check_reserved(&state, &trashed.short_link)?;
+    check_chain(
+        &state,
+        chain::Pending {
+            short_link: &trashed.short_link,
+            url: &trashed.url,
+            replaces: None,
+        },
+    )
+    .await?;
+
+    let restored = queries::restore_link(&state.pool, &short_link)
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to restore link"),
+            )
+        })?;
+    if !restored {
+        return Err(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Link not found in the trash"),
+        ));
+    }
+    state.index.catch_up().await;
+
+    match format {
+        ResponseFormat::Json => Ok(Json(fetch_link(&state, &short_link).await?).into_response()),
+        // Removes the trash row (or nothing, for the toast's undo button);
+        // the link list reloads to show the link where it belongs.
+        ResponseFormat::Html => Ok((
+            [(HX_TRIGGER, LINK_RESTORED)],
+            HtmlTemplate(ToastTemplate {
+                message: "Restored",
+                short_link: &short_link,
+                undo: false,
+            }),
+        )
+            .into_response()),
+    }
+}
+
+/// Permanently removes a link from the trash.
+pub async fn purge_link(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
+    AxumPath(short_link): AxumPath<String>,
+) -> Result<StatusCode, AppError> {
+    let trashed = fetch_trashed_link(&state, &short_link).await?;
+    viewer.require_owner_of(&trashed.short_link, trashed.owner.as_deref())?;
+    let purged = queries::purge_link(&state.pool, &short_link)
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to purge link"),
+            )
+        })?;
+    if !purged {
+        return Err(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Link not found in the trash"),
+        ));
+    }
+
+    if format == ResponseFormat::Json {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    Ok(StatusCode::OK)
+}
+
+async fn fetch_link(state: &AppState, short_link: &str) -> Result<Link, AppError> {
+    queries::fetch_link(&state.pool, short_link)
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to fetch link"),
+            )
+        })?
+        .ok_or_else(|| AppError(StatusCode::NOT_FOUND, anyhow::anyhow!("Link not found")))
+}
+
+pub async fn get_link(
+    State(state): State<AppState>,
+    format: ResponseFormat,
+    AxumPath(short_link): AxumPath<String>,
+) -> Result<Response, AppError> {
+    let link = fetch_link(&state, &short_link).await?;
+    match format {
+        ResponseFormat::Json => Ok(Json(link).into_response()),
+        ResponseFormat::Html => Ok(HtmlTemplate(LinkRowTemplate { link }).into_response()),
+    }
+}
+
+/// The link's details, including where it ends up when it points at other
+/// links.
+pub async fn show_link(
+    State(state): State<AppState>,
+    AxumPath(short_link): AxumPath<String>,
+) -> Result<Response, AppError> {
+    let link = fetch_link(&state, &short_link).await?;
+    let chain = follow_chain(&state, &link.short_link, None).await?;
+    Ok(HtmlTemplate(LinkDetailTemplate {
+        link,
+        chain,
+        max_depth: state.settings.max_chain_depth,
+    })
+    .into_response())
+}
+
+pub async fn edit_link(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    AxumPath(short_link): AxumPath<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let link = fetch_link(&state, &short_link).await?;
+    viewer.require_owner(&link)?;
+    Ok(HtmlTemplate(LinkEditRowTemplate { link }))
+}
+
+pub async fn update_link(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
+    AxumPath(short_link): AxumPath<String>,
+    Payload(update): Payload<LinkUpdate>,
+) -> Result<Response, AppError> {
+    let current = fetch_link(&state, &short_link).await?;
+    viewer.require_owner(&current)?;
+    let mut updated = update.apply(&current);
+    if let Err(e) = updated.validate() {
+        return Err(AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)));
+    }
+    // Existing links that already collide may keep their name until renamed.
+    if updated.short_link != current.short_link {
+        check_reserved(&state, &updated.short_link)?;
+    }
+    updated.url = check_url(&state, &updated.short_link, &updated.url)?;
+    check_chain(
+        &state,
+        chain::Pending {
+            short_link: &updated.short_link,
+            url: &updated.url,
+            replaces: Some(&current.short_link),
+        },
+    )
+    .await?;
+
+    let found = queries::update_link(&state.pool, &short_link, &updated)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => AppError(
+                StatusCode::CONFLICT,
+                anyhow::anyhow!("Short link '{}' already exists", updated.short_link),
+            ),
+            _ => AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to update link"),
+            ),
+        })?;
+
+    if !found {
+        return Err(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow::anyhow!("Link not found"),
+        ));
+    }
+    state.index.catch_up().await;
+
+    let link = fetch_link(&state, &updated.short_link).await?;
+    match format {
+        ResponseFormat::Json => Ok(Json(link).into_response()),
+        ResponseFormat::Html => Ok(HtmlTemplate(LinkRowTemplate { link }).into_response()),
+    }
+}
+
+pub async fn export_links(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, AppError> {
+    // Exports include everyone's links, like imports can overwrite them.
+    viewer.require_admin()?;
+    let links = transfer::fetch_all(&state.pool).await.map_err(|_| {
+        AppError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow::anyhow!("Failed to fetch links"),
+        )
+    })?;
+    let body = transfer::export(&links, params.format)
+        .map_err(|e| AppError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let disposition = format!(
+        "attachment; filename=\"to-links.{}\"",
+        params.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                params.format.content_type().to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+pub async fn import_links(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    Query(params): Query<ImportParams>,
+    body: String,
+) -> Result<Response, AppError> {
+    // Imports can overwrite anyone's links.
+    viewer.require_admin()?;
+    let links = transfer::parse(&body, params.format).map_err(|e| {
+        AppError(
+            StatusCode::BAD_REQUEST,
+            anyhow::anyhow!("Could not parse import file: {}", e),
+        )
+    })?;
+    let report = transfer::import(
+        &state.pool,
+        links,
+        params.policy,
+        params.dry_run,
+        viewer.username(),
+        &state.settings,
+    )
+    .await
+    .map_err(|e| {
+        error!("Import error: {:?}", e);
+        AppError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow::anyhow!("Failed to import links"),
+        )
+    })?;
+    if !params.dry_run {
+        state.index.invalidate().await;
+    }
+    Ok(Json(report).into_response())
+}
+
+pub async fn list_hosts(
+    State(state): State<AppState>,
+    format: ResponseFormat,
+) -> Result<Response, AppError> {
+    let hosts = queries::list_host_shortcuts(&state.pool)
+        .await
+        .map_err(|e| {
+            error!("Host list error: {:?}", e);
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to fetch host shortcuts"),
+            )
+        })?;
+    match format {
+        ResponseFormat::Json => Ok(Json(hosts).into_response()),
+        ResponseFormat::Html => Ok(HtmlTemplate(HostsListTemplate { hosts }).into_response()),
+    }
+}
+
+pub async fn add_host(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
+    Payload(mut new_host): Payload<NewHostShortcut>,
+) -> Result<Response, AppError> {
+    viewer.require_user()?;
+    if let Err(e) = new_host.validate() {
+        return Err(AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)));
+    }
+    new_host.url = state
+        .settings
+        .urls
+        .check_host_shortcut(&new_host.host, &new_host.url)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow::anyhow!(e)))?;
+
+    queries::insert_host_shortcut(&state.pool, &new_host, viewer.username())
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => AppError(
+                StatusCode::CONFLICT,
+                anyhow::anyhow!("Host shortcut '{}' already exists", new_host.host),
+            ),
+            _ => AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to create host shortcut"),
+            ),
+        })?;
+    if let Err(e) = state.hosts.reload().await {
+        error!("Failed to reload host shortcuts: {:?}", e);
+    }
+
+    let host = queries::fetch_host_shortcut(&state.pool, &new_host.host)
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to fetch host shortcut"),
+            )
+        })?;
+    match format {
+        ResponseFormat::Json => Ok((StatusCode::CREATED, Json(host)).into_response()),
+        ResponseFormat::Html => Ok(HtmlTemplate(HostRowTemplate { host }).into_response()),
+    }
+}
+
+pub async fn delete_host(
+    State(state): State<AppState>,
+    viewer: Viewer,
+    format: ResponseFormat,
+    AxumPath(host): AxumPath<String>,
+) -> Result<StatusCode, AppError> {
+    let shortcut = queries::fetch_host_shortcut(&state.pool, &host)
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to fetch host shortcut"),
+            )
+        })?
+        .ok_or_else(|| {
+            AppError(
+                StatusCode::NOT_FOUND,
+                anyhow::anyhow!("Host shortcut not found"),
+            )
+        })?;
+    viewer.require_owner_of(&shortcut.host, shortcut.owner.as_deref())?;
+
+    queries::delete_host_shortcut(&state.pool, &host)
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to delete host shortcut"),
+            )
+        })?;
+    if let Err(e) = state.hosts.reload().await {
+        error!("Failed to reload host shortcuts: {:?}", e);
+    }
+
+    if format == ResponseFormat::Json {
+        return Ok(StatusCode::NO_CONTENT);
+    }
     Ok(StatusCode::OK)
 }
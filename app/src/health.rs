@@ -0,0 +1,121 @@
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::{migrations, state::AppState};
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<(), String>) -> Self {
+        Self {
+            name,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: &'static str,
+    pub checks: Vec<Check>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(|c| c.ok)
+    }
+}
+
+/// The process is up and serving requests.
+pub async fn healthz() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Whether the database can serve traffic: 200 when every check passes,
+/// 503 otherwise, with the individual results either way.
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let readiness = check_readiness(&state.pool).await;
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+pub async fn check_readiness(pool: &SqlitePool) -> Readiness {
+    let checks = vec![
+        Check::new("database", check_database(pool).await),
+        Check::new("schema", check_schema(pool).await),
+        Check::new("search_index", check_indexed_links(pool).await),
+    ];
+    let status = if checks.iter().all(|c| c.ok) {
+        "ok"
+    } else {
+        "unavailable"
+    };
+    Readiness { status, checks }
+}
+
+pub async fn check_database(pool: &SqlitePool) -> Result<(), String> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Reads the schema version without writing, so probes never take the
+/// write lock.
+async fn check_schema(pool: &SqlitePool) -> Result<(), String> {
+    let current = migrations::applied_version(pool)
+        .await
+        .map_err(|e| format!("Schema is not initialized: {}", e))?;
+    let expected = migrations::latest_version();
+    if current == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "Schema version is {}, expected {}",
+            current, expected
+        ))
+    }
+}
+
+/// Counts the links the FTS index holds (one `links_fts_docsize` row each)
+/// against the live links it should hold. Reading the shadow table keeps
+/// this read-only; [`check_search_index`] compares the contents too.
+async fn check_indexed_links(pool: &SqlitePool) -> Result<(), String> {
+    let (indexed, live): (i64, i64) = sqlx::query_as(
+        "SELECT (SELECT COUNT(*) FROM links_fts_docsize), (SELECT COUNT(*) FROM live_links)",
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    if indexed == live {
+        Ok(())
+    } else {
+        Err(format!(
+            "Search index holds {} links, expected {}",
+            indexed, live
+        ))
+    }
+}
+
+/// Compares the FTS index against the `links` table it indexes. This writes
+/// to the database, so it runs at startup rather than in [`readyz`].
+pub async fn check_search_index(pool: &SqlitePool) -> Result<(), String> {
+    sqlx::query("INSERT INTO links_fts(links_fts, rank) VALUES('integrity-check', 1)")
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Search index is inconsistent: {}", e))
+}
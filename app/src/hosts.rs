@@ -0,0 +1,181 @@
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use percent_encoding::percent_decode_str;
+use sqlx::SqlitePool;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+use tracing::{debug, error};
+
+use crate::{index::WATCH_INTERVAL, models::HostShortcut, state::AppState, url_template};
+
+/// Suffix the LAN's resolver appends to bare hostnames.
+pub const LOCAL_DOMAIN: &str = "lan";
+
+/// Reduces a `Host` header or hostname to the bare shortcut name: lowercased,
+/// without port, trailing dot or `.lan` suffix.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let host = match host.strip_prefix('[') {
+        // IPv6 literal, e.g. `[::1]:3000`
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    match host
+        .strip_suffix(LOCAL_DOMAIN)
+        .and_then(|h| h.strip_suffix('.'))
+    {
+        Some(bare) => bare.to_string(),
+        None => host,
+    }
+}
+
+/// Every host shortcut's target, held in memory so requests don't wait for
+/// the database.
+///
+/// Loads itself on first use. Handlers that change shortcuts call
+/// [`HostShortcuts::reload`]; changes from elsewhere are picked up by
+/// [`HostShortcuts::watch`] through the `host_shortcuts_version` counter.
+#[derive(Clone)]
+pub struct HostShortcuts {
+    pool: SqlitePool,
+    loaded: Arc<RwLock<Option<Loaded>>>,
+}
+
+struct Loaded {
+    urls: HashMap<String, String>,
+    /// The `host_shortcuts_version` the URLs were loaded at.
+    version: i64,
+}
+
+impl HostShortcuts {
+    /// No shortcuts yet; they're loaded from `pool` when first used.
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            loaded: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// The target URL template of the shortcut for the normalized `host`.
+    pub async fn get(&self, host: &str) -> Result<Option<String>, sqlx::Error> {
+        if let Some(loaded) = self.loaded.read().await.as_ref() {
+            return Ok(loaded.urls.get(host).cloned());
+        }
+        let mut loaded = self.loaded.write().await;
+        if loaded.is_none() {
+            *loaded = Some(Loaded::load(&self.pool).await?);
+        }
+        Ok(loaded.as_ref().and_then(|l| l.urls.get(host).cloned()))
+    }
+
+    /// Loads every shortcut from the database again.
+    pub async fn reload(&self) -> Result<(), sqlx::Error> {
+        let fresh = Loaded::load(&self.pool).await?;
+        *self.loaded.write().await = Some(fresh);
+        Ok(())
+    }
+
+    /// Reloads the shortcuts whenever they changed since they were loaded,
+    /// checking every [`WATCH_INTERVAL`].
+    pub async fn watch(self) {
+        let mut ticker = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let Some(loaded) = self.loaded.read().await.as_ref().map(|l| l.version) else {
+                continue;
+            };
+            match current_version(&self.pool).await {
+                Ok(version) if version == loaded => {}
+                Ok(_) => match self.reload().await {
+                    Ok(()) => debug!("Reloaded the host shortcuts"),
+                    Err(e) => error!("Failed to reload the host shortcuts: {:?}", e),
+                },
+                Err(e) => error!("Failed to check the host shortcuts version: {:?}", e),
+            }
+        }
+    }
+}
+
+impl Loaded {
+    async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        // One transaction, so the version matches the shortcuts read.
+        let mut tx = pool.begin().await?;
+        let version = current_version(&mut *tx).await?;
+        let shortcuts: Vec<HostShortcut> =
+            sqlx::query_as("SELECT host, url, owner, created_at FROM host_shortcuts")
+                .fetch_all(&mut *tx)
+                .await?;
+        tx.commit().await?;
+        Ok(Self {
+            urls: shortcuts.into_iter().map(|s| (s.host, s.url)).collect(),
+            version,
+        })
+    }
+}
+
+async fn current_version<'e>(executor: impl sqlx::SqliteExecutor<'e>) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT version FROM host_shortcuts_version")
+        .fetch_one(executor)
+        .await
+}
+
+/// Redirects requests whose `Host` is a stored host shortcut (e.g.
+/// `http://maps/`) before they reach the regular routes. Path segments are
+/// passed to the target like extra segments of a short link.
+pub async fn redirect_host_shortcuts(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| request.uri().host())
+        .map(normalize_host)
+        .unwrap_or_default();
+    // Shortcuts saved before the app's own names were refused can't take over.
+    if host.is_empty() || state.settings.urls.is_own_hostname(&host) {
+        return next.run(request).await;
+    }
+
+    match state.hosts.get(&host).await {
+        Ok(Some(url)) => {
+            let segments: Vec<String> = request
+                .uri()
+                .path()
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+                .collect();
+            let args: Vec<&str> = segments.iter().map(String::as_str).collect();
+            Redirect::to(&url_template::expand(&url, &args)).into_response()
+        }
+        Ok(None) => next.run(request).await,
+        Err(e) => {
+            error!("Host shortcut lookup failed: {:?}", e);
+            next.run(request).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("maps"), "maps");
+        assert_eq!(normalize_host("Maps.LAN:80"), "maps");
+        assert_eq!(normalize_host("to.lan."), "to");
+        assert_eq!(normalize_host("wiki.example.com"), "wiki.example.com");
+        assert_eq!(normalize_host("[::1]:3000"), "::1");
+        assert_eq!(normalize_host("lan"), "lan");
+        assert_eq!(normalize_host("island"), "island");
+    }
+}
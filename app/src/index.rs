@@ -0,0 +1,580 @@
+use chrono::{NaiveDateTime, Utc};
+use sqlx::SqlitePool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::{RwLock, RwLockReadGuard};
+use tracing::{debug, error};
+
+use crate::{
+    analytics::HitRecorder,
+    chain::Links,
+    models::{Link, SearchParams, SortOrder},
+    queries,
+    search::{self, Match},
+    utils::levenshtein,
+};
+
+/// How often the database is checked for changes made by other processes
+/// (such as the command line).
+pub const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Names this many edits away from a search are candidates even when the
+/// search index doesn't match them (e.g. `gti` for `git`).
+const TYPO_DISTANCE: usize = 2;
+
+/// All live links, held in memory so redirects don't wait for the database
+/// and searches only ask it which links match.
+///
+/// The index loads itself from the database on first use. Handlers that
+/// change links call [`LinkIndex::catch_up`] once the change is committed;
+/// changes from elsewhere are picked up by [`LinkIndex::watch`] through the
+/// `links_version` counter. Either way, only the links named in
+/// `link_changes` since the index's version are read again.
+#[derive(Clone)]
+pub struct LinkIndex {
+    pool: SqlitePool,
+    hits: HitRecorder,
+    entries: Arc<RwLock<Option<Entries>>>,
+    /// Held while catching up, so changes are applied in the order they
+    /// were read.
+    catching_up: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// How long `link_changes` rows are kept for indexes to catch up from.
+const CHANGES_KEPT: &str = "-1 hour";
+
+impl LinkIndex {
+    /// An empty index, loaded from `pool` when first used. Hits queued in
+    /// `hits` are written before each load, so the counters include them.
+    pub fn new(pool: SqlitePool, hits: HitRecorder) -> Self {
+        Self {
+            pool,
+            hits,
+            entries: Arc::new(RwLock::new(None)),
+            catching_up: Arc::default(),
+        }
+    }
+
+    /// The loaded entries, loading them first on a cold start.
+    async fn read(&self) -> Result<RwLockReadGuard<'_, Entries>, sqlx::Error> {
+        {
+            let entries = self.entries.read().await;
+            if entries.is_some() {
+                return Ok(RwLockReadGuard::map(entries, |e| e.as_ref().unwrap()));
+            }
+        }
+        let mut entries = self.entries.write().await;
+        if entries.is_none() {
+            *entries = Some(self.load().await?);
+        }
+        Ok(RwLockReadGuard::map(entries.downgrade(), |e| {
+            e.as_ref().unwrap()
+        }))
+    }
+
+    /// Loads every link from the database again.
+    pub async fn reload(&self) -> Result<(), sqlx::Error> {
+        let mut entries = self.entries.write().await;
+        *entries = Some(self.load().await?);
+        Ok(())
+    }
+
+    async fn load(&self) -> Result<Entries, sqlx::Error> {
+        self.hits.flush().await;
+        Entries::load(&self.pool).await
+    }
+
+    /// Drops the entries after many links changed, so the next lookup loads
+    /// them again.
+    pub async fn invalidate(&self) {
+        *self.entries.write().await = None;
+    }
+
+    /// Re-reads the links changed since the index was loaded or last caught
+    /// up, after the app changed some or [`watch`](Self::watch) saw others
+    /// change them. Reloads everything when those changes were pruned from
+    /// `link_changes`, and does nothing before the index is loaded, as loading
+    /// will see the changes.
+    pub async fn catch_up(&self) {
+        let _catching_up = self.catching_up.lock().await;
+        let Some(from) = self.entries.read().await.as_ref().map(|e| e.version) else {
+            return;
+        };
+        // Read the rows before locking, so redirects aren't held up meanwhile.
+        // Hits are written first, as the rows read replace the counted ones.
+        self.hits.flush().await;
+        let changes = match read_changes(&self.pool, from).await {
+            Ok(Some(changes)) => changes,
+            Ok(None) => {
+                match self.reload().await {
+                    Ok(()) => debug!("Reloaded the link index"),
+                    Err(e) => error!("Failed to reload the link index: {:?}", e),
+                }
+                return;
+            }
+            Err(e) => {
+                // Start over from the database rather than serve stale links.
+                error!("Failed to catch up the link index, dropping it: {:?}", e);
+                *self.entries.write().await = None;
+                return;
+            }
+        };
+        {
+            let mut entries = self.entries.write().await;
+            // A reload in the meantime already saw the changes, and maybe newer ones.
+            let Some(loaded) = entries.as_mut().filter(|e| e.version == from) else {
+                return;
+            };
+            for (name, link) in changes.links {
+                match link {
+                    Some(link) => loaded.insert(link),
+                    None => loaded.remove(&name),
+                }
+            }
+            loaded.version = changes.version;
+        }
+        if let Err(e) = prune_changes(&self.pool).await {
+            error!("Failed to prune link changes: {:?}", e);
+        }
+    }
+
+    /// Counts a resolution of `short_link`, in memory and through the
+    /// [`HitRecorder`]. Both happen under the read lock, so a reload (which
+    /// writes the queued hits first) sees the hit either in the database or
+    /// not at all.
+    pub async fn record_hit(&self, short_link: &str) {
+        let entries = self.entries.read().await;
+        self.hits.record(short_link);
+        if let Some(entries) = entries.as_ref()
+            && entries.links.contains_key(short_link)
+        {
+            let mut counted = entries.counted.lock().unwrap();
+            let counted = counted.entry(short_link.to_string()).or_default();
+            counted.hits += 1;
+            counted.last_accessed = Some(Utc::now().naive_utc());
+        }
+    }
+
+    /// Whether the index has seen every change to the links, or isn't loaded.
+    pub async fn is_current(&self) -> Result<bool, sqlx::Error> {
+        let Some(loaded) = self.entries.read().await.as_ref().map(|e| e.version) else {
+            return Ok(true);
+        };
+        Ok(current_version(&self.pool).await? == loaded)
+    }
+
+    pub async fn get(&self, short_link: &str) -> Result<Option<Link>, sqlx::Error> {
+        let entries = self.read().await?;
+        let counted = entries.counted.lock().unwrap();
+        Ok(entries
+            .links
+            .get(short_link)
+            .map(|link| with_hits(link, &counted)))
+    }
+
+    /// Names of links at most `max_distance` edits away from `name`, closest
+    /// first.
+    pub async fn similar(
+        &self,
+        name: &str,
+        max_distance: usize,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let entries = self.read().await?;
+        let mut similar = entries.names.within(name, max_distance);
+        similar.sort();
+        Ok(similar
+            .into_iter()
+            .map(|(_, name)| name.to_string())
+            .collect())
+    }
+
+    /// Like [`queries::search_links`], with the links read from memory: one
+    /// page of links and the number of links across all pages. Both the web and the command line
+    /// search through here.
+    ///
+    /// Candidates are the links `links_fts` finds for the query's
+    /// [`fts_match`](search::SearchQuery::fts_match), plus names a couple of
+    /// typos away; every link when it has nothing to look up. They are then
+    /// checked against [`SearchQuery::admits`](search::SearchQuery::admits)
+    /// and ranked by [`search::rank`], with their `bm25` rank in the index as
+    /// the relevance.
+    pub async fn search(&self, params: &SearchParams) -> Result<(Vec<Link>, u64), sqlx::Error> {
+        let query = params.query();
+        let fts = match query.fts_match() {
+            Some(fts) if query.has_terms() => {
+                Some(queries::fts_candidates(&self.pool, &fts).await?)
+            }
+            _ => None,
+        };
+        let entries = self.read().await?;
+        let counted = entries.counted.lock().unwrap();
+        let hits = |link: &Link| match counted.get(&link.short_link) {
+            Some(c) => (link.hits + c.hits, link.last_accessed.max(c.last_accessed)),
+            None => (link.hits, link.last_accessed),
+        };
+        let wanted = |link: &Link| {
+            query.tags.iter().all(|t| link.tags.contains(t))
+                && !query.excluded_tags.iter().any(|t| link.tags.contains(t))
+                && query.admits(&link.short_link, &link.url, &link.description)
+        };
+        let page = |names: Vec<&str>| -> (Vec<Link>, u64) {
+            let total = names.len() as u64;
+            let links = names
+                .into_iter()
+                .skip(params.offset() as usize)
+                .take(params.limit as usize)
+                .map(|name| with_hits(&entries.links[name], &counted))
+                .collect();
+            (links, total)
+        };
+
+        if !query.has_terms() {
+            let mut links: Vec<&Link> = entries.links.values().filter(|l| wanted(l)).collect();
+            links.sort_by(|a, b| {
+                let by_sort = match params.sort {
+                    SortOrder::Recent => b.created_at.cmp(&a.created_at),
+                    SortOrder::Popular => {
+                        hits(b).cmp(&hits(a)).then(b.created_at.cmp(&a.created_at))
+                    }
+                };
+                by_sort.then_with(|| a.short_link.cmp(&b.short_link))
+            });
+            return Ok(page(links.iter().map(|l| l.short_link.as_str()).collect()));
+        }
+
+        let mut matched: HashMap<&str, f64> = match fts {
+            // Links the database has but the index doesn't yet are left out.
+            Some(found) => found
+                .into_iter()
+                .filter_map(|(name, rank)| {
+                    let (name, _) = entries.links.get_key_value(&name)?;
+                    Some((name.as_str(), rank))
+                })
+                .collect(),
+            None => entries
+                .links
+                .keys()
+                .map(|name| (name.as_str(), 0.0))
+                .collect(),
+        };
+        if !query.text.is_empty() {
+            for (_, name) in entries.names.within(&query.text, TYPO_DISTANCE) {
+                matched.entry(name).or_insert(0.0);
+            }
+        }
+
+        let mut matches: Vec<Match> = matched
+            .into_iter()
+            .map(|(name, rank)| (&entries.links[name], rank))
+            .filter(|(link, _)| wanted(link))
+            .map(|(link, rank)| Match {
+                short_link: link.short_link.clone(),
+                url: link.url.clone(),
+                description: link.description.clone(),
+                rank,
+                hits: hits(link).0,
+            })
+            .collect();
+        search::rank(&mut matches, &query, params.sort);
+        Ok(page(
+            matches.iter().map(|m| m.short_link.as_str()).collect(),
+        ))
+    }
+
+    /// Catches up whenever the links changed since the index was loaded,
+    /// checking every [`WATCH_INTERVAL`]. This picks up changes from other
+    /// processes; the app's own changes were caught up with already.
+    pub async fn watch(self) {
+        let mut ticker = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match self.is_current().await {
+                Ok(true) => {}
+                Ok(false) => self.catch_up().await,
+                Err(e) => error!("Failed to check the links version: {:?}", e),
+            }
+        }
+    }
+}
+
+impl Links for LinkIndex {
+    async fn targets(&mut self, names: &[String]) -> Result<HashMap<String, String>, sqlx::Error> {
+        let entries = self.read().await?;
+        Ok(names
+            .iter()
+            .filter_map(|name| Some((name.clone(), entries.links.get(name)?.url.clone())))
+            .collect())
+    }
+
+    async fn all_targets(&mut self) -> Result<HashMap<String, String>, sqlx::Error> {
+        let entries = self.read().await?;
+        Ok(entries
+            .links
+            .values()
+            .map(|link| (link.short_link.clone(), link.url.clone()))
+            .collect())
+    }
+}
+
+async fn current_version(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT version FROM links_version")
+        .fetch_one(pool)
+        .await
+}
+
+/// The links changed since some version, as they are now (`None` when
+/// deleted), and the version that brings an index to.
+struct Changes {
+    version: i64,
+    links: Vec<(String, Option<Link>)>,
+}
+
+/// The changes since version `from`, or `None` when some of them were
+/// pruned already.
+async fn read_changes(pool: &SqlitePool, from: i64) -> Result<Option<Changes>, sqlx::Error> {
+    // One transaction, so the version matches the links read.
+    let mut tx = pool.begin().await?;
+    let version: i64 = sqlx::query_scalar("SELECT version FROM links_version")
+        .fetch_one(&mut *tx)
+        .await?;
+    let mut links = Vec::new();
+    if version != from {
+        let oldest: Option<i64> =
+            sqlx::query_scalar("SELECT MIN(version) FROM link_changes WHERE version > ?")
+                .bind(from)
+                .fetch_one(&mut *tx)
+                .await?;
+        if oldest != Some(from + 1) {
+            return Ok(None);
+        }
+        let names: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT short_link FROM link_changes WHERE version > ? AND version <= ?",
+        )
+        .bind(from)
+        .bind(version)
+        .fetch_all(&mut *tx)
+        .await?;
+        for name in names {
+            let link = sqlx::query_as(&format!("{} WHERE l.short_link = ?", Link::SELECT))
+                .bind(&name)
+                .fetch_optional(&mut *tx)
+                .await?;
+            links.push((name, link));
+        }
+    }
+    tx.commit().await?;
+    Ok(Some(Changes { version, links }))
+}
+
+async fn prune_changes(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM link_changes WHERE changed_at < datetime('now', ?)")
+        .bind(CHANGES_KEPT)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+struct Entries {
+    links: HashMap<String, Link>,
+    names: BkTree,
+    /// The `links_version` the entries were loaded at.
+    version: i64,
+    /// Hits counted since then, kept apart so counting one only needs a
+    /// read lock on the entries.
+    counted: Mutex<HashMap<String, Counted>>,
+}
+
+#[derive(Default)]
+struct Counted {
+    hits: i64,
+    last_accessed: Option<NaiveDateTime>,
+}
+
+impl Entries {
+    async fn load(pool: &SqlitePool) -> Result<Self, sqlx::Error> {
+        // One transaction, so the version matches the links read.
+        let mut tx = pool.begin().await?;
+        let version: i64 = sqlx::query_scalar("SELECT version FROM links_version")
+            .fetch_one(&mut *tx)
+            .await?;
+        let links: Vec<Link> = sqlx::query_as(Link::SELECT).fetch_all(&mut *tx).await?;
+        tx.commit().await?;
+
+        let mut entries = Entries {
+            links: HashMap::with_capacity(links.len()),
+            names: BkTree::default(),
+            version,
+            counted: Mutex::default(),
+        };
+        for link in links {
+            entries.insert(link);
+        }
+        Ok(entries)
+    }
+
+    fn insert(&mut self, link: Link) {
+        self.remove(&link.short_link);
+        self.names.insert(&link.short_link);
+        self.links.insert(link.short_link.clone(), link);
+    }
+
+    fn remove(&mut self, short_link: &str) {
+        // A re-read link starts over from the hits stored so far.
+        self.counted.get_mut().unwrap().remove(short_link);
+        if self.links.remove(short_link).is_some() {
+            self.names.remove(short_link);
+        }
+    }
+}
+
+/// A copy of `link` including the hits in `counted`.
+fn with_hits(link: &Link, counted: &HashMap<String, Counted>) -> Link {
+    let mut link = link.clone();
+    if let Some(c) = counted.get(&link.short_link) {
+        link.hits += c.hits;
+        link.last_accessed = link.last_accessed.max(c.last_accessed);
+    }
+    link
+}
+
+/// A BK-tree of link names: each child sits at its edit distance from its
+/// parent, so a lookup only visits branches that can be close enough.
+///
+/// Names are placed by the Levenshtein distance of their lowercase forms,
+/// and matched like [`search::TYPOS`] ranks them. That one counts a swap of
+/// two letters as a single edit, which isn't a metric the tree can prune
+/// by, but it is never less than half the Levenshtein distance, so looking
+/// twice as far finds every match.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    /// The lowercase name the node is placed by.
+    key: String,
+    /// Live names with that key. Nodes left without any stay in place (to
+    /// keep their children reachable) until the index is reloaded.
+    names: Vec<String>,
+    children: HashMap<usize, usize>,
+}
+
+impl BkTree {
+    fn insert(&mut self, name: &str) {
+        let key = name.to_lowercase();
+        if self.nodes.is_empty() {
+            self.nodes.push(BkNode::new(key, name));
+            return;
+        }
+        let mut at = 0;
+        loop {
+            let distance = levenshtein(&self.nodes[at].key, &key);
+            if distance == 0 {
+                let names = &mut self.nodes[at].names;
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+                return;
+            }
+            match self.nodes[at].children.get(&distance) {
+                Some(&child) => at = child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(BkNode::new(key, name));
+                    self.nodes[at].children.insert(distance, child);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        let key = name.to_lowercase();
+        if let Some(node) = self.nodes.iter_mut().find(|n| n.key == key) {
+            node.names.retain(|n| n != name);
+        }
+    }
+
+    /// Live names at most `max_distance` typos from `name`, with their
+    /// distance.
+    fn within(&self, name: &str, max_distance: usize) -> Vec<(usize, &str)> {
+        let key = name.to_lowercase();
+        let reach = 2 * max_distance;
+        let typos = search::TYPOS.max(max_distance);
+        let mut found = Vec::new();
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(at) = stack.pop() {
+            let node = &self.nodes[at];
+            let distance = levenshtein(&node.key, &key);
+            if distance <= reach {
+                found.extend(node.names.iter().filter_map(|n| {
+                    let typos = typos.between(n, name)?;
+                    Some((typos, n.as_str()))
+                }));
+            }
+            let range = distance.saturating_sub(reach)..=distance + reach;
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| range.contains(d))
+                    .map(|(_, &child)| child),
+            );
+        }
+        found
+    }
+}
+
+impl BkNode {
+    fn new(key: String, name: &str) -> Self {
+        Self {
+            key,
+            names: vec![name.to_string()],
+            children: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bk_tree() {
+        let mut tree = BkTree::default();
+        for name in ["git", "gist", "grafana", "jira", "wiki", "wiki2"] {
+            tree.insert(name);
+        }
+        // A swap is one typo, as the ranking counts it
+        assert_eq!(tree.within("gti", 1), [(1, "git")]);
+        let mut found = tree.within("gis", 1);
+        found.sort();
+        assert_eq!(found, [(1, "gist"), (1, "git")]);
+        let mut found = tree.within("wiki", 1);
+        found.sort();
+        assert_eq!(found, [(0, "wiki"), (1, "wiki2")]);
+
+        tree.remove("wiki");
+        assert_eq!(tree.within("wiki", 1), [(1, "wiki2")]);
+        tree.insert("wiki");
+        assert_eq!(tree.within("wiki", 0), [(0, "wiki")]);
+
+        // Case doesn't count, and names differing only in case share a node
+        assert_eq!(tree.within("GRAFNA", 1), [(1, "grafana")]);
+        tree.insert("Wiki");
+        let mut found = tree.within("WIKI", 0);
+        found.sort();
+        assert_eq!(found, [(0, "Wiki"), (0, "wiki")]);
+        tree.remove("wiki");
+        assert_eq!(tree.within("wiki", 0), [(0, "Wiki")]);
+        // Two swaps are two typos but four Levenshtein edits
+        tree.insert("abcdef");
+        assert_eq!(tree.within("badcef", 2), [(2, "abcdef")]);
+    }
+}
@@ -1,15 +1,41 @@
+pub mod analytics;
+pub mod api;
+pub mod auth;
+pub mod chain;
+pub mod cli;
+pub mod config;
+pub mod dns;
 pub mod error;
 pub mod handlers;
+pub mod health;
+pub mod hosts;
+pub mod index;
+pub mod metrics;
+pub mod migrations;
 pub mod models;
+pub mod queries;
+pub mod render;
+pub mod reserved;
+pub mod sd_notify;
+pub mod search;
 pub mod state;
 pub mod templates;
+pub mod transfer;
+pub mod trash;
+pub mod url_policy;
+pub mod url_template;
 pub mod utils;
 
-use crate::handlers::{add_link, delete_link, list_links, redirect_link, show_ui};
+use crate::handlers::{
+    add_host, add_link, delete_host, delete_link, edit_link, export_links, get_link, import_links,
+    list_hosts, list_links, list_trash, login, logout, opensearch_description,
+    opensearch_suggestions, purge_link, redirect_link, restore_link, search, show_link, show_login,
+    show_ui, update_link,
+};
 use crate::state::AppState;
 use axum::{
     Router,
-    routing::{delete, get},
+    routing::{MethodRouter, delete, get, post},
 };
 use sqlx::{
     SqlitePool,
@@ -17,45 +43,86 @@ use sqlx::{
 };
 use std::str::FromStr;
 
+/// Every route the app serves. Short link names are checked against the
+/// literal prefixes of these paths (see [`reserved::ReservedNames`]).
+fn routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/link", get(show_ui)),
+        ("/link/{*short_link}", get(show_link)),
+        ("/login", get(show_login).post(login)),
+        ("/logout", post(logout)),
+        ("/search", get(search)),
+        ("/opensearch.xml", get(opensearch_description)),
+        ("/{short_link}", get(redirect_link)),
+        ("/{short_link}/{*rest}", get(redirect_link)),
+        ("/api/links", get(list_links).post(add_link)),
+        (
+            "/api/links/{short_link}",
+            get(get_link)
+                .put(update_link)
+                .patch(update_link)
+                .delete(delete_link),
+        ),
+        ("/api/links/{short_link}/edit", get(edit_link)),
+        ("/api/suggest", get(opensearch_suggestions)),
+        ("/api/trash", get(list_trash)),
+        ("/api/trash/{short_link}", delete(purge_link)),
+        ("/api/trash/{short_link}/restore", post(restore_link)),
+        ("/api/export", get(export_links)),
+        ("/api/import", post(import_links)),
+        ("/api/hosts", get(list_hosts).post(add_host)),
+        ("/api/hosts/{host}", delete(delete_host)),
+        ("/metrics", get(metrics::show_metrics)),
+        ("/healthz", get(health::healthz)),
+        ("/readyz", get(health::readyz)),
+    ]
+}
+
+/// The paths of all routes, as passed to the router.
+pub fn route_paths() -> Vec<&'static str> {
+    routes().into_iter().map(|(path, _)| path).collect()
+}
+
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        .route("/link", get(show_ui))
-        .route("/{short_link}", get(redirect_link))
-        .route("/api/links", get(list_links).post(add_link))
-        .route("/api/links/{short_link}", delete(delete_link))
+    routes()
+        .into_iter()
+        .fold(Router::new(), |router, (path, handler)| {
+            router.route(path, handler)
+        })
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            hosts::redirect_host_shortcuts,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            metrics::track_requests,
+        ))
         .with_state(state)
+        .layer(axum::middleware::from_fn(api::json_errors))
         .layer(tower_http::trace::TraceLayer::new_for_http())
 }
 
-pub async fn setup_db(db_path: &str) -> anyhow::Result<SqlitePool> {
+/// Connections kept by the pool unless configured otherwise.
+pub const DEFAULT_POOL_SIZE: u32 = 5;
+
+/// Opens (creating if needed) the SQLite database without touching its schema.
+pub async fn connect_db(db_path: &str) -> anyhow::Result<SqlitePool> {
+    connect_db_with(db_path, DEFAULT_POOL_SIZE).await
+}
+
+/// Like [`connect_db`], with at most `max_connections` open connections.
+pub async fn connect_db_with(db_path: &str, max_connections: u32) -> anyhow::Result<SqlitePool> {
     let db_options = SqliteConnectOptions::from_str(db_path)?.create_if_missing(true);
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(max_connections)
         .connect_with(db_options)
         .await?;
+    Ok(pool)
+}
 
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS links (
-            short_link TEXT PRIMARY KEY NOT NULL,
-            url TEXT NOT NULL,
-            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-        );
-        CREATE VIRTUAL TABLE IF NOT EXISTS links_fts USING fts5(short_link, url, content='links', content_rowid='rowid', tokenize='trigram');
-        CREATE TRIGGER IF NOT EXISTS links_ai AFTER INSERT ON links BEGIN
-            INSERT INTO links_fts(rowid, short_link, url) VALUES (new.rowid, new.short_link, new.url);
-        END;
-        CREATE TRIGGER IF NOT EXISTS links_ad AFTER DELETE ON links BEGIN
-            INSERT INTO links_fts(links_fts, rowid, short_link, url) VALUES('delete', old.rowid, old.short_link, old.url);
-        END;
-        CREATE TRIGGER IF NOT EXISTS links_au AFTER UPDATE ON links BEGIN
-            INSERT INTO links_fts(links_fts, rowid, short_link, url) VALUES('delete', old.rowid, old.short_link, old.url);
-            INSERT INTO links_fts(rowid, short_link, url) VALUES (new.rowid, new.short_link, new.url);
-        END;
-        "#,
-    )
-    .execute(&pool)
-    .await?;
-
+/// Opens the database and brings its schema up to date.
+pub async fn setup_db(db_path: &str) -> anyhow::Result<SqlitePool> {
+    let pool = connect_db(db_path).await?;
+    migrations::migrate(&pool).await?;
     Ok(pool)
 }
@@ -1,51 +1,24 @@
 use clap::Parser;
-use std::net::SocketAddr;
-use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use app::{create_router, setup_db, state::AppState};
-
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    #[arg(long)]
-    db: Option<String>,
-    #[arg(long, default_value = "3000")]
-    port: u16,
-}
+use app::cli::{self, Args};
+use app::config::LogFormat;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "app=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
     let args = Args::parse();
-
-    let db_path = match args.db {
-        Some(path) => path,
-        None => {
-            let home_dir =
-                dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-            let default_path = home_dir.join(".local/share/to-links");
-            std::fs::create_dir_all(&default_path)?;
-            default_path.join("app.db").to_string_lossy().to_string()
-        }
-    };
-    info!("Using database at: {}", db_path);
-
-    let pool = setup_db(&db_path).await?;
-    let app_state = AppState { pool };
-    let app = create_router(app_state);
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], args.port));
-    info!("Listening on {}", addr);
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
-
-    Ok(())
+    let (config, config_path) = cli::load_config(&args)?;
+
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "app=debug,tower_http=debug".into());
+    let fmt = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    match config.log_format {
+        LogFormat::Text => tracing_subscriber::registry().with(filter).with(fmt).init(),
+        LogFormat::Json => tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt.json())
+            .init(),
+    }
+
+    cli::run(args, config, config_path).await
 }
@@ -0,0 +1,358 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::error;
+
+use crate::{
+    error::{AppError, ErrorInfo},
+    state::AppState,
+};
+
+const PREFIX: &str = "to_links";
+
+/// Upper bounds (in seconds) of the latency histogram buckets.
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Label used for requests that matched no route.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Non-cumulative count per bucket, plus one for `+Inf`.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, sep, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, sep, self.count
+        );
+        let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces(labels), self.count);
+    }
+}
+
+/// How a short link request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RedirectOutcome {
+    Hit,
+    Miss,
+    /// The link's chain loops or is too long.
+    Loop,
+    /// The lookup itself failed.
+    Error,
+}
+
+impl RedirectOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            RedirectOutcome::Hit => "hit",
+            RedirectOutcome::Miss => "miss",
+            RedirectOutcome::Loop => "loop",
+            RedirectOutcome::Error => "error",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// Keyed by (method, route, status).
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Keyed by (method, route).
+    request_durations: BTreeMap<(String, String), Histogram>,
+    redirects: BTreeMap<RedirectOutcome, u64>,
+    search_durations: Histogram,
+}
+
+/// In-process counters and histograms, rendered in the Prometheus text
+/// format by `/metrics`. Gauges (pool usage, link count) are read at scrape
+/// time instead of being tracked here.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        registry
+            .request_durations
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn record_redirect(&self, outcome: RedirectOutcome) {
+        *self
+            .registry
+            .lock()
+            .unwrap()
+            .redirects
+            .entry(outcome)
+            .or_default() += 1;
+    }
+
+    pub fn observe_search(&self, elapsed: Duration) {
+        self.registry
+            .lock()
+            .unwrap()
+            .search_durations
+            .observe(elapsed);
+    }
+
+    /// Renders the collected metrics, followed by the given gauges.
+    pub fn render(&self, gauges: &[Gauge]) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        let name = format!("{}_http_requests_total", PREFIX);
+        header(
+            &mut out,
+            &name,
+            "counter",
+            "HTTP requests by route and status.",
+        );
+        for ((method, route, status), count) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "{}{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                name,
+                escape(method),
+                escape(route),
+                status,
+                count
+            );
+        }
+
+        let name = format!("{}_http_request_duration_seconds", PREFIX);
+        header(
+            &mut out,
+            &name,
+            "histogram",
+            "HTTP request latency by route.",
+        );
+        for ((method, route), histogram) in &registry.request_durations {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            histogram.write(&mut out, &name, &labels);
+        }
+
+        let name = format!("{}_redirects_total", PREFIX);
+        header(
+            &mut out,
+            &name,
+            "counter",
+            "Short link lookups, by how they ended.",
+        );
+        for outcome in [
+            RedirectOutcome::Hit,
+            RedirectOutcome::Miss,
+            RedirectOutcome::Loop,
+            RedirectOutcome::Error,
+        ] {
+            let count = registry.redirects.get(&outcome).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "{}{{outcome=\"{}\"}} {}",
+                name,
+                outcome.as_str(),
+                count
+            );
+        }
+
+        let name = format!("{}_search_duration_seconds", PREFIX);
+        header(&mut out, &name, "histogram", "Link search latency.");
+        registry.search_durations.write(&mut out, &name, "");
+
+        for gauge in gauges {
+            let name = format!("{}_{}", PREFIX, gauge.name);
+            header(&mut out, &name, "gauge", gauge.help);
+            let _ = writeln!(out, "{} {}", name, gauge.value);
+        }
+        out
+    }
+}
+
+/// A value sampled at scrape time.
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: f64,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str, value: f64) -> Self {
+        Self { name, help, value }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn braces(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    }
+}
+
+/// Escapes a label value as the text format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Counts every request and its latency under the route it matched (e.g.
+/// `/api/links/{short_link}`), so label values stay bounded. Errors count
+/// with their real status, even though HTMX receives client errors as 200
+/// (see [`AppError`]).
+pub async fn track_requests(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    let status = match response.extensions().get::<ErrorInfo>() {
+        Some(info) => info.status,
+        None => response.status(),
+    };
+    state
+        .metrics
+        .observe_request(&method, &route, status.as_u16(), start.elapsed());
+    response
+}
+
+pub async fn show_metrics(State(state): State<AppState>) -> Result<Response, AppError> {
+    let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM live_links")
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| {
+            error!("Link count error: {:?}", e);
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow::anyhow!("Failed to collect metrics"),
+            )
+        })?;
+
+    let pool = &state.pool;
+    let idle = pool.num_idle() as f64;
+    let open = pool.size() as f64;
+    let gauges = [
+        Gauge::new("links", "Stored short links.", links as f64),
+        Gauge::new(
+            "db_pool_max_connections",
+            "Configured SQLite pool size.",
+            pool.options().get_max_connections() as f64,
+        ),
+        Gauge::new("db_pool_open_connections", "Open SQLite connections.", open),
+        Gauge::new(
+            "db_pool_idle_connections",
+            "Open SQLite connections not in use.",
+            idle,
+        ),
+        Gauge::new(
+            "db_pool_active_connections",
+            "SQLite connections currently in use.",
+            (open - idle).max(0.0),
+        ),
+    ];
+
+    Ok((
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        state.metrics.render(&gauges),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(30));
+        histogram.observe(Duration::from_secs(10));
+
+        let mut out = String::new();
+        histogram.write(&mut out, "h", "route=\"/x\"");
+        assert!(out.contains("h_bucket{route=\"/x\",le=\"0.001\"} 1\n"));
+        assert!(out.contains("h_bucket{route=\"/x\",le=\"0.025\"} 1\n"));
+        assert!(out.contains("h_bucket{route=\"/x\",le=\"0.05\"} 2\n"));
+        assert!(out.contains("h_bucket{route=\"/x\",le=\"5\"} 2\n"));
+        assert!(out.contains("h_bucket{route=\"/x\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("h_count{route=\"/x\"} 3\n"));
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.observe_request("GET", "/{short_link}", 303, Duration::from_millis(2));
+        metrics.observe_request("GET", "/{short_link}", 303, Duration::from_millis(3));
+        metrics.record_redirect(RedirectOutcome::Hit);
+
+        let out = metrics.render(&[Gauge::new("links", "Stored short links.", 7.0)]);
+        assert!(out.contains(
+            "to_links_http_requests_total{method=\"GET\",route=\"/{short_link}\",status=\"303\"} 2\n"
+        ));
+        assert!(out.contains("to_links_redirects_total{outcome=\"hit\"} 1\n"));
+        assert!(out.contains("to_links_redirects_total{outcome=\"miss\"} 0\n"));
+        assert!(out.contains("# TYPE to_links_links gauge\nto_links_links 7\n"));
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
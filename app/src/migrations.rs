@@ -0,0 +1,143 @@
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+use tracing::info;
+
+/// A numbered schema change. Migrations are applied in order, each in its own
+/// transaction, and recorded in the `schema_version` table.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial schema",
+        sql: include_str!("../migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "link stats",
+        sql: include_str!("../migrations/0002_link_stats.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "descriptions and tags",
+        sql: include_str!("../migrations/0003_descriptions_tags.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "users and link owners",
+        sql: include_str!("../migrations/0004_users_owners.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "host shortcuts",
+        sql: include_str!("../migrations/0005_host_shortcuts.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "soft delete",
+        sql: include_str!("../migrations/0006_soft_delete.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "links version",
+        sql: include_str!("../migrations/0007_links_version.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "host shortcuts version",
+        sql: include_str!("../migrations/0008_host_shortcuts_version.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "link changes",
+        sql: include_str!("../migrations/0009_link_changes.sql"),
+    },
+];
+
+/// The schema version this build expects.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+async fn ensure_version_table(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Returns the highest applied migration, or 0 for an unversioned database.
+pub async fn current_version(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    version_of(&mut *pool.acquire().await?).await
+}
+
+/// Like [`current_version`], without creating the `schema_version` table:
+/// a database that has never been migrated is an error.
+pub async fn applied_version<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+) -> Result<i64, sqlx::Error> {
+    let (version,): (Option<i64>,) = sqlx::query_as("SELECT MAX(version) FROM schema_version")
+        .fetch_one(executor)
+        .await?;
+    Ok(version.unwrap_or(0))
+}
+
+async fn version_of(conn: &mut SqliteConnection) -> Result<i64, sqlx::Error> {
+    ensure_version_table(conn).await?;
+    applied_version(conn).await
+}
+
+/// Applies every pending migration and returns the ones that ran.
+pub async fn migrate(pool: &SqlitePool) -> anyhow::Result<Vec<&'static Migration>> {
+    // All on one connection: the pool would otherwise open another whenever
+    // the previous one isn't back yet, and a connection opened halfway
+    // through can fail later with "no such table".
+    let mut conn = pool.acquire().await?;
+    let current = version_of(&mut conn).await?;
+    if current > latest_version() {
+        anyhow::bail!(
+            "Database schema version {} is newer than this build supports ({})",
+            current,
+            latest_version()
+        );
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        info!(
+            "Applying migration {}: {}",
+            migration.version, migration.name
+        );
+        let mut tx = conn.begin().await?;
+        sqlx::raw_sql(migration.sql).execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i64 + 1);
+        }
+    }
+}
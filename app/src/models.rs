@@ -1,16 +1,95 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(sqlx::FromRow, Debug, Clone)]
+use crate::search::SearchQuery;
+
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
 pub struct Link {
     pub short_link: String,
     pub url: String,
+    pub description: String,
+    #[sqlx(json)]
+    pub tags: Vec<String>,
     pub created_at: chrono::NaiveDateTime,
+    pub hits: i64,
+    pub last_accessed: Option<chrono::NaiveDateTime>,
+    /// The user who created the link, when authentication is enabled.
+    pub owner: Option<String>,
 }
 
-#[derive(Deserialize)]
+impl Link {
+    /// Selects every [`Link`] column from `live_links l` (links not in the
+    /// trash) joined with its usage stats and its tags (as a sorted JSON array).
+    pub const SELECT: &'static str = "SELECT l.short_link, l.url, l.description, \
+         (SELECT json_group_array(name) FROM (SELECT t.name FROM link_tags lt \
+             JOIN tags t ON t.id = lt.tag_id WHERE lt.short_link = l.short_link ORDER BY t.name)) AS tags, \
+         l.created_at, COALESCE(s.hits, 0) AS hits, s.last_accessed, l.owner \
+         FROM live_links l LEFT JOIN link_stats s ON s.short_link = l.short_link";
+}
+
+/// A deleted link, kept until it is restored or purged.
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct TrashedLink {
+    pub short_link: String,
+    pub url: String,
+    pub description: String,
+    pub owner: Option<String>,
+    pub deleted_at: chrono::NaiveDateTime,
+}
+
+#[derive(Deserialize, Default)]
 pub struct NewLink {
     pub short_link: String,
     pub url: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<String>,
+}
+
+/// Accepts tags either as a list or as a single comma/space separated string
+/// (the form encoding), normalized with [`normalize_tags`].
+pub fn deserialize_tags<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Tags {
+        List(Vec<String>),
+        Text(String),
+    }
+    Ok(match Tags::deserialize(deserializer)? {
+        Tags::List(tags) => normalize_tags(tags),
+        Tags::Text(text) => parse_tags(&text),
+    })
+}
+
+fn deserialize_optional_tags<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_tags(deserializer).map(Some)
+}
+
+/// Splits a comma or whitespace separated tag list.
+pub fn parse_tags(text: &str) -> Vec<String> {
+    normalize_tags(
+        text.split(|c: char| c == ',' || c.is_whitespace())
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+/// Lowercases, strips a leading `#`, drops empties and duplicates, and sorts.
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().trim_start_matches('#').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
 }
 
 impl NewLink {
@@ -21,21 +100,147 @@ impl NewLink {
         let is_valid = self
             .short_link
             .chars()
-            .all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == ':');
+            .all(|c| c.is_alphanumeric() || c == '.' || c == '-' || c == ':' || c == '/');
+        if self.short_link.split('/').any(|segment| segment.is_empty()) {
+            return Err("Short link segments cannot be empty");
+        }
         if !is_valid {
             return Err("Invalid characters in short link");
         }
+        crate::url_template::validate(&self.url)?;
+        let tags_valid = self.tags.iter().all(|tag| {
+            tag.chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+        });
+        if !tags_valid {
+            return Err("Invalid characters in tag");
+        }
         Ok(())
     }
 }
 
+/// Changes to an existing link. Missing fields keep their current value.
 #[derive(Deserialize)]
+pub struct LinkUpdate {
+    pub short_link: Option<String>,
+    pub url: Option<String>,
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_optional_tags")]
+    pub tags: Option<Vec<String>>,
+}
+
+impl LinkUpdate {
+    /// Applies the update on top of `link`, producing the link to be stored.
+    pub fn apply(self, link: &Link) -> NewLink {
+        NewLink {
+            short_link: self
+                .short_link
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| link.short_link.clone()),
+            url: self
+                .url
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| link.url.clone()),
+            description: self
+                .description
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|| link.description.clone()),
+            tags: self.tags.unwrap_or_else(|| link.tags.clone()),
+        }
+    }
+}
+
+/// A whole hostname (e.g. `maps`) that redirects, instead of a path under `to`.
+#[derive(sqlx::FromRow, Serialize, Deserialize, Debug, Clone)]
+pub struct HostShortcut {
+    pub host: String,
+    pub url: String,
+    pub owner: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Deserialize, Default)]
+pub struct NewHostShortcut {
+    pub host: String,
+    pub url: String,
+}
+
+impl NewHostShortcut {
+    /// Normalizes the hostname (see [`crate::hosts::normalize_host`]) and
+    /// checks both fields.
+    pub fn validate(&mut self) -> Result<(), &'static str> {
+        self.host = crate::hosts::normalize_host(&self.host);
+        if self.host.is_empty() {
+            return Err("Host cannot be empty");
+        }
+        let is_valid = self.host.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+        if !is_valid {
+            return Err("Invalid characters in host");
+        }
+        crate::url_template::validate(&self.url)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct SearchParams {
     pub q: Option<String>,
     #[serde(default = "default_page")]
     pub page: u32,
     #[serde(default = "default_limit")]
     pub limit: u32,
+    #[serde(default)]
+    pub sort: SortOrder,
+}
+
+impl SearchParams {
+    /// The search box query in `q`.
+    pub fn query(&self) -> SearchQuery {
+        SearchQuery::parse(self.q.as_deref().unwrap_or_default())
+    }
+
+    /// Links on the pages before this one.
+    pub fn offset(&self) -> u32 {
+        self.page.saturating_sub(1).saturating_mul(self.limit)
+    }
+
+    /// Whether links remain after this page, out of `total`.
+    pub fn has_next(&self, total: u64) -> bool {
+        u64::from(self.offset()) + u64::from(self.limit) < total
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Newest links first; search results by relevance.
+    #[default]
+    Recent,
+    /// Most used links first.
+    Popular,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Recent => "recent",
+            SortOrder::Popular => "popular",
+        }
+    }
+}
+
+/// One page of links, as returned by the JSON API.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LinksPage {
+    pub links: Vec<Link>,
+    pub page: u32,
+    pub limit: u32,
+    /// Links matching the query across all pages.
+    pub total: u64,
+    pub has_next: bool,
+    pub q: String,
+    pub sort: SortOrder,
 }
 
 fn default_page() -> u32 {
@@ -55,31 +260,99 @@ mod tests {
         let valid_link = NewLink {
             short_link: "my-link".to_string(),
             url: "https://example.com".to_string(),
+            ..Default::default()
         };
         assert!(valid_link.validate().is_ok());
 
         let valid_link_dots = NewLink {
             short_link: "my.link".to_string(),
             url: "https://example.com".to_string(),
+            ..Default::default()
         };
         assert!(valid_link_dots.validate().is_ok());
 
         let invalid_chars = NewLink {
             short_link: "my link".to_string(), // space is invalid
             url: "https://example.com".to_string(),
+            ..Default::default()
         };
         assert!(invalid_chars.validate().is_err());
 
         let empty_link = NewLink {
             short_link: "".to_string(),
             url: "https://example.com".to_string(),
+            ..Default::default()
         };
         assert!(empty_link.validate().is_err());
 
         let invalid_symbol = NewLink {
             short_link: "link!".to_string(),
             url: "https://example.com".to_string(),
+            ..Default::default()
         };
         assert!(invalid_symbol.validate().is_err());
+
+        let nested_link = NewLink {
+            short_link: "gh/rust".to_string(),
+            url: "https://github.com/rust-lang/{*}".to_string(),
+            ..Default::default()
+        };
+        assert!(nested_link.validate().is_ok());
+
+        let empty_segment = NewLink {
+            short_link: "gh//rust".to_string(),
+            url: "https://github.com".to_string(),
+            ..Default::default()
+        };
+        assert!(empty_segment.validate().is_err());
+
+        let trailing_slash = NewLink {
+            short_link: "gh/".to_string(),
+            url: "https://github.com".to_string(),
+            ..Default::default()
+        };
+        assert!(trailing_slash.validate().is_err());
+
+        let bad_template = NewLink {
+            short_link: "jira".to_string(),
+            url: "https://jira.example/browse/{id}".to_string(),
+            ..Default::default()
+        };
+        assert!(bad_template.validate().is_err());
+
+        let bad_tag = NewLink {
+            short_link: "ci".to_string(),
+            url: "https://ci.example".to_string(),
+            tags: vec!["in fra".to_string()],
+            ..Default::default()
+        };
+        assert!(bad_tag.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags("Infra, #deploy  infra,,ci"),
+            vec!["ci", "deploy", "infra"]
+        );
+        assert!(parse_tags(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_host_shortcut_validation() {
+        let mut shortcut = NewHostShortcut {
+            host: "Maps.LAN".to_string(),
+            url: "https://maps.google.com".to_string(),
+        };
+        assert!(shortcut.validate().is_ok());
+        assert_eq!(shortcut.host, "maps");
+
+        for host in ["", "a b", "x/y", "a..b"] {
+            let mut shortcut = NewHostShortcut {
+                host: host.to_string(),
+                url: "https://example.com".to_string(),
+            };
+            assert!(shortcut.validate().is_err(), "{:?}", host);
+        }
     }
 }
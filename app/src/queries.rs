@@ -0,0 +1,385 @@
+use crate::{
+    models::{HostShortcut, Link, NewHostShortcut, NewLink, SearchParams, SortOrder, TrashedLink},
+    search::{self, Match},
+};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+
+/// Loads a single link by its exact short name.
+pub async fn fetch_link(pool: &SqlitePool, short_link: &str) -> Result<Option<Link>, sqlx::Error> {
+    sqlx::query_as(&format!("{} WHERE l.short_link = ?", Link::SELECT))
+        .bind(short_link)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn insert_link(
+    pool: &SqlitePool,
+    link: &NewLink,
+    owner: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    purge_trashed(&mut tx, &link.short_link).await?;
+    sqlx::query("INSERT INTO links (short_link, url, description, owner) VALUES (?, ?, ?, ?)")
+        .bind(&link.short_link)
+        .bind(&link.url)
+        .bind(&link.description)
+        .bind(owner)
+        .execute(&mut *tx)
+        .await?;
+    set_tags(&mut tx, &link.short_link, &link.tags).await?;
+    tx.commit().await
+}
+
+/// Replaces the link stored as `short_link` with `link`, possibly renaming it.
+/// Returns whether the link existed.
+pub async fn update_link(
+    pool: &SqlitePool,
+    short_link: &str,
+    link: &NewLink,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    if link.short_link != short_link {
+        purge_trashed(&mut tx, &link.short_link).await?;
+    }
+    // A single UPDATE keeps the rowid (and created_at), the links_au trigger
+    // moves the FTS entry, and foreign keys cascade the rename to stats and tags.
+    let result = sqlx::query(
+        "UPDATE links SET short_link = ?, url = ?, description = ? \
+         WHERE short_link = ? AND deleted_at IS NULL",
+    )
+    .bind(&link.short_link)
+    .bind(&link.url)
+    .bind(&link.description)
+    .bind(short_link)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+    set_tags(&mut tx, &link.short_link, &link.tags).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Replaces the tags of a link, creating new tags and dropping unused ones.
+pub async fn set_tags(
+    tx: &mut Transaction<'_, Sqlite>,
+    short_link: &str,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM link_tags WHERE short_link = ?")
+        .bind(short_link)
+        .execute(&mut **tx)
+        .await?;
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO link_tags (short_link, tag_id) SELECT ?, id FROM tags WHERE name = ?",
+        )
+        .bind(short_link)
+        .bind(tag)
+        .execute(&mut **tx)
+        .await?;
+    }
+    drop_unused_tags(tx).await
+}
+
+/// SQL condition restricting `links l` to links carrying every one of
+/// `count` tags, bound as parameters.
+fn tag_filter(count: usize) -> String {
+    format!(
+        "l.short_link IN (SELECT lt.short_link FROM link_tags lt JOIN tags t ON t.id = lt.tag_id \
+         WHERE t.name IN ({}) GROUP BY lt.short_link HAVING COUNT(*) = {})",
+        vec!["?"; count].join(", "),
+        count
+    )
+}
+
+/// SQL condition restricting `links l` to links carrying none of `count`
+/// tags, bound as parameters.
+fn excluded_tag_filter(count: usize) -> String {
+    format!(
+        "l.short_link NOT IN (SELECT lt.short_link FROM link_tags lt JOIN tags t ON t.id = lt.tag_id \
+         WHERE t.name IN ({}))",
+        vec!["?"; count].join(", ")
+    )
+}
+
+/// Moves a link to the trash, returning whether it existed.
+pub async fn delete_link(pool: &SqlitePool, short_link: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE links SET deleted_at = CURRENT_TIMESTAMP \
+         WHERE short_link = ? AND deleted_at IS NULL",
+    )
+    .bind(short_link)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+const TRASHED_SELECT: &str = "SELECT short_link, url, description, owner, deleted_at FROM links WHERE deleted_at IS NOT NULL";
+
+/// Lists the trash, most recently deleted first.
+pub async fn list_trash(pool: &SqlitePool) -> Result<Vec<TrashedLink>, sqlx::Error> {
+    sqlx::query_as(&format!("{} ORDER BY deleted_at DESC", TRASHED_SELECT))
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn fetch_trashed_link(
+    pool: &SqlitePool,
+    short_link: &str,
+) -> Result<Option<TrashedLink>, sqlx::Error> {
+    sqlx::query_as(&format!("{} AND short_link = ?", TRASHED_SELECT))
+        .bind(short_link)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Takes a link back out of the trash, returning whether it was there.
+pub async fn restore_link(pool: &SqlitePool, short_link: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE links SET deleted_at = NULL WHERE short_link = ? AND deleted_at IS NOT NULL",
+    )
+    .bind(short_link)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Permanently removes a link from the trash, returning whether it was there.
+pub async fn purge_link(pool: &SqlitePool, short_link: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let purged = purge_trashed(&mut tx, short_link).await?;
+    tx.commit().await?;
+    Ok(purged)
+}
+
+/// Permanently removes links deleted more than `days` days ago, returning
+/// how many there were.
+pub async fn purge_trash(pool: &SqlitePool, days: u32) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
+        "DELETE FROM links WHERE deleted_at IS NOT NULL \
+         AND deleted_at <= datetime('now', '-' || ? || ' days')",
+    )
+    .bind(days)
+    .execute(&mut *tx)
+    .await?;
+    drop_unused_tags(&mut tx).await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Permanently removes `short_link` if it is in the trash, so the name can
+/// be reused. Stats and tags go with it.
+pub async fn purge_trashed(
+    tx: &mut Transaction<'_, Sqlite>,
+    short_link: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM links WHERE short_link = ? AND deleted_at IS NOT NULL")
+        .bind(short_link)
+        .execute(&mut **tx)
+        .await?;
+    drop_unused_tags(tx).await?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn drop_unused_tags(tx: &mut Transaction<'_, Sqlite>) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM link_tags)")
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+pub async fn fetch_host_shortcut(
+    pool: &SqlitePool,
+    host: &str,
+) -> Result<Option<HostShortcut>, sqlx::Error> {
+    sqlx::query_as("SELECT host, url, owner, created_at FROM host_shortcuts WHERE host = ?")
+        .bind(host)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn list_host_shortcuts(pool: &SqlitePool) -> Result<Vec<HostShortcut>, sqlx::Error> {
+    sqlx::query_as("SELECT host, url, owner, created_at FROM host_shortcuts ORDER BY host")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn insert_host_shortcut(
+    pool: &SqlitePool,
+    shortcut: &NewHostShortcut,
+    owner: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO host_shortcuts (host, url, owner) VALUES (?, ?, ?)")
+        .bind(&shortcut.host)
+        .bind(&shortcut.url)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Deletes a host shortcut, returning whether it existed.
+pub async fn delete_host_shortcut(pool: &SqlitePool, host: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM host_shortcuts WHERE host = ?")
+        .bind(host)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Names of the live links `links_fts` finds for an FTS5 query built by
+/// [`fts_match`](search::SearchQuery::fts_match), with their `bm25` rank.
+pub async fn fts_candidates(
+    pool: &SqlitePool,
+    fts: &str,
+) -> Result<Vec<(String, f64)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT l.short_link, f.rank FROM live_links l JOIN links_fts f ON l.link_rowid = f.rowid
+         WHERE links_fts MATCH ?",
+    )
+    .bind(fts)
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns one page of links and the number of links across all pages.
+///
+/// Without search terms, links are listed by `params.sort`. With them, every
+/// link the trigram FTS index matches (see
+/// [`fts_match`](search::SearchQuery::fts_match)) is
+/// checked against the query's phrases and fields, then ranked by
+/// [`search::rank`] (index relevance, closeness to the name or URL and
+/// popularity) before the page is cut, so every match is reachable. `tag:`
+/// and `-tag:` terms restrict both.
+pub async fn search_links(
+    pool: &SqlitePool,
+    params: &SearchParams,
+) -> Result<(Vec<Link>, u64), sqlx::Error> {
+    let limit = params.limit;
+    let offset = params.offset();
+    let query = params.query();
+    let mut conditions = Vec::new();
+    if !query.tags.is_empty() {
+        conditions.push(tag_filter(query.tags.len()));
+    }
+    if !query.excluded_tags.is_empty() {
+        conditions.push(excluded_tag_filter(query.excluded_tags.len()));
+    }
+    let tags = query.tags.iter().chain(&query.excluded_tags);
+
+    if query.has_terms() {
+        let fts = query.fts_match();
+        let (rank, from) = if fts.is_some() {
+            conditions.insert(0, "links_fts MATCH ?".to_string());
+            (
+                "f.rank",
+                "live_links l JOIN links_fts f ON l.link_rowid = f.rowid",
+            )
+        } else {
+            ("0.0", "live_links l")
+        };
+        let sql = format!(
+            "SELECT l.short_link, l.url, l.description, {} AS rank, COALESCE(s.hits, 0) AS hits
+             FROM {}
+             LEFT JOIN link_stats s ON s.short_link = l.short_link
+             {}",
+            rank,
+            from,
+            where_clause(&conditions)
+        );
+        let mut matches = sqlx::query_as::<_, Match>(&sql);
+        if let Some(fts) = fts {
+            matches = matches.bind(fts);
+        }
+        for tag in tags {
+            matches = matches.bind(tag);
+        }
+        let mut matches = matches.fetch_all(pool).await?;
+        matches.retain(|m| query.admits(&m.short_link, &m.url, &m.description));
+        search::rank(&mut matches, &query, params.sort);
+
+        let total = matches.len() as u64;
+        let page: Vec<&str> = matches
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|m| m.short_link.as_str())
+            .collect();
+        if page.is_empty() {
+            return Ok((Vec::new(), total));
+        }
+        let sql = format!(
+            "{} WHERE l.short_link IN ({})",
+            Link::SELECT,
+            vec!["?"; page.len()].join(", ")
+        );
+        let mut links = sqlx::query_as::<_, Link>(&sql);
+        for name in &page {
+            links = links.bind(*name);
+        }
+        let mut links = links.fetch_all(pool).await?;
+        links.sort_by_key(|l| page.iter().position(|name| *name == l.short_link));
+        Ok((links, total))
+    } else {
+        let filter = where_clause(&conditions);
+        let order_by = match params.sort {
+            SortOrder::Recent => "l.created_at DESC, l.short_link",
+            SortOrder::Popular => {
+                "hits DESC, s.last_accessed DESC, l.created_at DESC, l.short_link"
+            }
+        };
+        if !query.excluded.is_empty() {
+            // Excluded terms are checked here, so the page is cut here too.
+            let sql = format!("{} {} ORDER BY {}", Link::SELECT, filter, order_by);
+            let mut listing = sqlx::query_as::<_, Link>(&sql);
+            for tag in tags {
+                listing = listing.bind(tag);
+            }
+            let mut links = listing.fetch_all(pool).await?;
+            links.retain(|l| query.admits(&l.short_link, &l.url, &l.description));
+            let total = links.len() as u64;
+            let links = links
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect();
+            return Ok((links, total));
+        }
+
+        let sql = format!(
+            "{} {} ORDER BY {} LIMIT ? OFFSET ?",
+            Link::SELECT,
+            filter,
+            order_by
+        );
+        let mut listing = sqlx::query_as::<_, Link>(&sql);
+        for tag in tags.clone() {
+            listing = listing.bind(tag);
+        }
+        let links = listing.bind(limit).bind(offset).fetch_all(pool).await?;
+
+        let sql = format!("SELECT COUNT(*) FROM live_links l {}", filter);
+        let mut count = sqlx::query_scalar::<_, i64>(&sql);
+        for tag in tags {
+            count = count.bind(tag);
+        }
+        let total = count.fetch_one(pool).await?;
+        Ok((links, total as u64))
+    }
+}
+
+/// `WHERE` with `conditions` AND-ed together, if there are any.
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    }
+}
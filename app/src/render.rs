@@ -0,0 +1,172 @@
+use std::fmt::Write;
+use std::net::IpAddr;
+
+use crate::auth::AuthMode;
+
+/// Deployment config files `render-config` can produce.
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ConfigKind {
+    Nginx,
+    Dnsmasq,
+    Systemd,
+}
+
+/// Everything the config files are rendered from: the server settings and
+/// the host shortcuts stored in the database.
+#[derive(Debug, Clone)]
+pub struct RenderContext {
+    /// Hostnames served by the app: its own names (`to`) followed by the
+    /// host shortcuts, all without `.lan`.
+    pub hostnames: Vec<String>,
+    /// Address the hostnames resolve to.
+    pub address: IpAddr,
+    /// Where nginx reaches the app.
+    pub app_host: String,
+    pub port: u16,
+    /// Absolute path of the binary.
+    pub exec: String,
+    /// Account the service runs as.
+    pub user: Option<String>,
+    /// Arguments for `serve`, including `--db` and `--port`.
+    pub serve_args: Vec<String>,
+    /// How the app identifies users, and the header it reads them from under
+    /// [`AuthMode::Proxy`].
+    pub auth_mode: AuthMode,
+    pub auth_header: String,
+}
+
+pub fn render(kind: ConfigKind, ctx: &RenderContext) -> String {
+    match kind {
+        ConfigKind::Nginx => nginx(ctx),
+        ConfigKind::Dnsmasq => dnsmasq(ctx),
+        ConfigKind::Systemd => systemd(ctx),
+    }
+}
+
+/// Each hostname followed by its `.lan` variant.
+fn with_lan_variants(hostnames: &[String]) -> Vec<String> {
+    hostnames
+        .iter()
+        .flat_map(|h| [h.clone(), format!("{}.{}", h, crate::hosts::LOCAL_DOMAIN)])
+        .collect()
+}
+
+pub fn nginx(ctx: &RenderContext) -> String {
+    let mut out = String::new();
+    out.push_str("# Generated by `to-links-app render-config nginx`.\n");
+    out.push_str("# Every shortcut hostname is proxied to the app, which tells short links\n");
+    out.push_str("# and host shortcuts apart by the Host header. As the default server it also\n");
+    out.push_str("# catches host shortcuts added after this file was generated.\n");
+    out.push_str("server {\n");
+    out.push_str("    listen 80 default_server;\n");
+    let _ = writeln!(
+        out,
+        "    server_name {};",
+        with_lan_variants(&ctx.hostnames).join(" ")
+    );
+    out.push('\n');
+    out.push_str("    location / {\n");
+    let _ = writeln!(
+        out,
+        "        proxy_pass http://{}:{};",
+        ctx.app_host, ctx.port
+    );
+    out.push_str("        proxy_set_header Host $host;\n");
+    out.push_str("        proxy_set_header X-Real-IP $remote_addr;\n");
+    // The identity header is always overwritten, so clients can't supply
+    // their own.
+    if ctx.auth_mode == AuthMode::Proxy {
+        out.push_str(
+            "        # The app trusts this header: authenticate here (e.g. auth_basic).\n",
+        );
+        let _ = writeln!(
+            out,
+            "        proxy_set_header {} $remote_user;",
+            ctx.auth_header
+        );
+    } else {
+        out.push_str("        # Drop any identity header a client sends.\n");
+        let _ = writeln!(out, "        proxy_set_header {} \"\";", ctx.auth_header);
+    }
+    out.push_str("    }\n");
+    out.push_str("}\n");
+    out
+}
+
+pub fn dnsmasq(ctx: &RenderContext) -> String {
+    let mut out = String::new();
+    out.push_str("# Generated by `to-links-app render-config dnsmasq`.\n");
+    out.push_str("# Map the shortcut hostnames to this server.\n");
+    for name in with_lan_variants(&ctx.hostnames) {
+        let _ = writeln!(out, "address=/{}/{}", name, ctx.address);
+    }
+    out
+}
+
+pub fn systemd(ctx: &RenderContext) -> String {
+    let working_dir = std::path::Path::new(&ctx.exec)
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let mut exec_start = vec![quote(&ctx.exec), "serve".to_string()];
+    exec_start.extend(ctx.serve_args.iter().map(|a| quote(a)));
+
+    let mut out = String::new();
+    out.push_str("# Generated by `to-links-app render-config systemd`.\n");
+    out.push_str("[Unit]\n");
+    out.push_str("Description=To-Links Shortener Service\n");
+    out.push_str("After=network.target\n");
+    out.push('\n');
+    out.push_str("[Service]\n");
+    out.push_str("# The app reports readiness and pings the watchdog while the database answers\n");
+    out.push_str("Type=notify\n");
+    out.push_str("NotifyAccess=main\n");
+    out.push_str("WatchdogSec=30\n");
+    if let Some(user) = &ctx.user {
+        let _ = writeln!(out, "User={}", user);
+    }
+    let _ = writeln!(out, "WorkingDirectory={}", working_dir);
+    let _ = writeln!(out, "ExecStart={}", exec_start.join(" "));
+    out.push_str("# Lets --dns-listen bind port 53 without running as root\n");
+    out.push_str("AmbientCapabilities=CAP_NET_BIND_SERVICE\n");
+    out.push_str("Restart=always\n");
+    out.push_str("Environment=RUST_LOG=info\n");
+    out.push('\n');
+    out.push_str("[Install]\n");
+    out.push_str("WantedBy=multi-user.target\n");
+    out
+}
+
+/// Quotes a word for a systemd command line when it needs it.
+fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./:=,@[]".contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!(
+            "\"{}\"",
+            word.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('%', "%%")
+                .replace('$', "$$")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("--port"), "--port");
+        assert_eq!(quote("/home/pi/app.db"), "/home/pi/app.db");
+        assert_eq!(quote("/home/my pi/app.db"), "\"/home/my pi/app.db\"");
+        assert_eq!(quote("50%"), "\"50%%\"");
+        assert_eq!(quote("$HOME"), "\"$$HOME\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+}
@@ -0,0 +1,95 @@
+use sqlx::SqlitePool;
+use std::collections::BTreeSet;
+
+/// Names short links can't take because the router serves them itself, plus
+/// any configured extras (e.g. paths another service on the Pi handles).
+///
+/// A link is reserved when its first segment is: `api/foo` would be
+/// shadowed by the `/api/...` routes just like `api` would.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReservedNames(BTreeSet<String>);
+
+impl Default for ReservedNames {
+    fn default() -> Self {
+        Self::from_routes()
+    }
+}
+
+impl ReservedNames {
+    /// The leading literal segment of every route in [`crate::create_router`].
+    pub fn from_routes() -> Self {
+        let names = crate::route_paths()
+            .into_iter()
+            .filter_map(|path| path.trim_start_matches('/').split('/').next())
+            .filter(|segment| !segment.is_empty() && !segment.starts_with('{'))
+            .map(str::to_string)
+            .collect();
+        Self(names)
+    }
+
+    pub fn with_extras(mut self, extras: &[String]) -> Self {
+        self.0.extend(
+            extras
+                .iter()
+                .map(|name| name.trim().trim_matches('/').to_string())
+                .filter(|name| !name.is_empty()),
+        );
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// The reserved name that `short_link` collides with, if any.
+    pub fn conflict(&self, short_link: &str) -> Option<&str> {
+        let first = short_link.split('/').next().unwrap_or_default();
+        self.0.get(first).map(String::as_str)
+    }
+
+    pub fn check(&self, short_link: &str) -> Result<(), String> {
+        match self.conflict(short_link) {
+            Some(name) => Err(format!(
+                "Short link '{}' is reserved: /{} is served by the app",
+                short_link, name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Stored links that can't be reached because of a reserved name.
+    pub async fn find_conflicts(&self, pool: &SqlitePool) -> Result<Vec<String>, sqlx::Error> {
+        let names: Vec<String> =
+            sqlx::query_scalar("SELECT short_link FROM live_links ORDER BY short_link")
+                .fetch_all(pool)
+                .await?;
+        Ok(names
+            .into_iter()
+            .filter(|name| self.conflict(name).is_some())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_names() {
+        let reserved = ReservedNames::from_routes();
+        for name in ["link", "api", "login", "metrics", "healthz", "readyz"] {
+            assert!(reserved.names().any(|n| n == name), "{}", name);
+        }
+        assert!(!reserved.names().any(|n| n.starts_with('{')));
+
+        assert!(reserved.check("api").is_err());
+        assert!(reserved.check("api/links").is_err());
+        assert!(reserved.check("apis").is_ok());
+        assert!(reserved.check("grafana").is_ok());
+        assert!(reserved.check("Link").is_ok());
+
+        let reserved = reserved.with_extras(&["/favicon.ico".to_string(), " ".to_string()]);
+        assert_eq!(reserved.conflict("favicon.ico"), Some("favicon.ico"));
+        assert!(!reserved.names().any(str::is_empty));
+    }
+}
@@ -0,0 +1,541 @@
+use url::Url;
+
+use crate::{
+    models::{SortOrder, normalize_tags},
+    utils::EditDistance,
+};
+
+/// How much each signal contributes to a match's [`score`].
+const CLOSENESS_WEIGHT: f64 = 0.5;
+const RELEVANCE_WEIGHT: f64 = 0.3;
+const POPULARITY_WEIGHT: f64 = 0.2;
+
+/// How names are compared with the query for closeness, and for typo
+/// candidates in the [`LinkIndex`](crate::index::LinkIndex): `Gti` is as
+/// close to `git` as a single typo.
+pub const TYPOS: EditDistance = EditDistance::new().transpositions().ignore_case();
+
+/// The trigram index can't look up anything shorter; such terms are only
+/// checked by [`SearchQuery::admits`].
+const MIN_INDEXED_LEN: usize = 3;
+
+/// A parsed search box query.
+///
+/// - Free words (`kube ctl`) match fuzzily, so typos still find links.
+/// - `"a phrase"` must appear as is in the name, URL or description.
+/// - `name:`, `url:` and `host:` terms must appear in that part of the link,
+///   e.g. `host:grafana.internal` or `url:"/d/cluster"`.
+/// - `tag:infra` keeps links tagged `infra`; several must all match.
+/// - A leading `-` excludes links instead: `-host:staging`, `-tag:old`.
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Phrases and field terms every match contains.
+    pub required: Vec<Term>,
+    /// Terms no match contains.
+    pub excluded: Vec<Term>,
+    pub tags: Vec<String>,
+    pub excluded_tags: Vec<String>,
+}
+
+/// The part of a link a [`Term`] is looked for in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    /// The name, URL or description.
+    Any,
+    Name,
+    Url,
+    /// Only the URL's host.
+    Host,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub field: Field,
+    pub text: String,
+}
+
+impl SearchQuery {
+    pub fn parse(q: &str) -> Self {
+        let mut query = SearchQuery::default();
+        let mut words = Vec::new();
+        let (mut tags, mut excluded_tags) = (Vec::new(), Vec::new());
+        let mut rest = q.trim_start();
+        while !rest.is_empty() {
+            let (token, after) = Token::next(rest);
+            rest = after.trim_start();
+            if token.text.is_empty() {
+                continue;
+            }
+            let field = match token.prefix {
+                Some("tag") if token.negated => {
+                    excluded_tags.push(token.text);
+                    continue;
+                }
+                Some("tag") => {
+                    tags.push(token.text);
+                    continue;
+                }
+                Some("name") => Field::Name,
+                Some("url") => Field::Url,
+                Some("host") => Field::Host,
+                _ if !token.negated && !token.quoted => {
+                    words.push(token.text);
+                    continue;
+                }
+                _ => Field::Any,
+            };
+            let term = Term {
+                field,
+                text: token.text,
+            };
+            if token.negated {
+                query.excluded.push(term);
+            } else {
+                query.required.push(term);
+            }
+        }
+        query.text = words.join(" ");
+        query.tags = normalize_tags(tags);
+        query.excluded_tags = normalize_tags(excluded_tags);
+        query
+    }
+
+    /// Whether the query asks for anything besides tags, so links are ranked
+    /// against it rather than listed.
+    pub fn has_terms(&self) -> bool {
+        !self.text.is_empty() || !self.required.is_empty()
+    }
+
+    /// An FTS5 query finding the candidates: free words broken into trigrams
+    /// OR-ed together ("kubernetes" -> "kub" OR "ube" OR ...), AND-ed with
+    /// column filters for the other terms. `None` when there is nothing the
+    /// index can look up, so every link is a candidate.
+    ///
+    /// Both [`queries::search_links`](crate::queries::search_links) and the
+    /// [`LinkIndex`](crate::index::LinkIndex) find their candidates with it.
+    pub fn fts_match(&self) -> Option<String> {
+        let mut required = Vec::new();
+        let text: Vec<char> = self.text.chars().collect();
+        if text.len() > MIN_INDEXED_LEN {
+            let trigrams: Vec<String> = text
+                .windows(MIN_INDEXED_LEN)
+                .map(|w| quote(&w.iter().collect::<String>()))
+                .collect();
+            required.push(format!("({})", trigrams.join(" OR ")));
+        } else if text.len() == MIN_INDEXED_LEN {
+            required.push(quote(&self.text));
+        }
+        required.extend(self.required.iter().filter_map(Term::fts_filter));
+        if required.is_empty() {
+            return None;
+        }
+        let mut fts = required.join(" AND ");
+
+        // Hosts can't be told apart from the rest of the URL here.
+        let excluded: Vec<String> = self
+            .excluded
+            .iter()
+            .filter(|t| t.field != Field::Host)
+            .filter_map(Term::fts_filter)
+            .collect();
+        if !excluded.is_empty() {
+            fts = format!("({}) NOT ({})", fts, excluded.join(" OR "));
+        }
+        Some(fts)
+    }
+
+    /// Whether a link satisfies the phrase, field and excluded terms, and
+    /// contains free words too short for the index to look up. Longer free
+    /// words and tags are matched separately.
+    pub fn admits(&self, short_link: &str, url: &str, description: &str) -> bool {
+        let found = |t: &Term| t.found_in(short_link, url, description);
+        let length = self.text.chars().count();
+        let short_text = (0 < length && length < MIN_INDEXED_LEN).then(|| Term {
+            field: Field::Any,
+            text: self.text.clone(),
+        });
+        self.required.iter().chain(&short_text).all(found) && !self.excluded.iter().any(found)
+    }
+
+    /// What ranking measures closeness against: the free words, and each
+    /// required term in its field.
+    fn targets(&self) -> impl Iterator<Item = (Field, &str)> {
+        let text = (!self.text.is_empty()).then_some((Field::Any, self.text.as_str()));
+        text.into_iter()
+            .chain(self.required.iter().map(|t| (t.field, t.text.as_str())))
+    }
+}
+
+impl Term {
+    /// The FTS5 column filter for this term, if long enough to look up.
+    fn fts_filter(&self) -> Option<String> {
+        if self.text.chars().count() < MIN_INDEXED_LEN {
+            return None;
+        }
+        let column = match self.field {
+            Field::Any => return Some(quote(&self.text)),
+            Field::Name => "short_link",
+            Field::Url | Field::Host => "url",
+        };
+        Some(format!("{} : {}", column, quote(&self.text)))
+    }
+
+    fn found_in(&self, short_link: &str, url: &str, description: &str) -> bool {
+        let text = self.text.to_lowercase();
+        let contains = |haystack: &str| haystack.to_lowercase().contains(&text);
+        match self.field {
+            Field::Any => contains(short_link) || contains(url) || contains(description),
+            Field::Name => contains(short_link),
+            Field::Url => contains(url),
+            Field::Host => Url::parse(url)
+                .ok()
+                .and_then(|u| u.host_str().map(contains))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// One search box token: `[-][field:]word` or `[-][field:]"a phrase"`.
+struct Token<'a> {
+    negated: bool,
+    prefix: Option<&'a str>,
+    text: String,
+    quoted: bool,
+}
+
+impl<'a> Token<'a> {
+    /// Splits the token at the start of `s` from the rest.
+    fn next(s: &'a str) -> (Self, &'a str) {
+        let (negated, s) = match s.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() && !rest.starts_with(char::is_whitespace) => {
+                (true, rest)
+            }
+            _ => (false, s),
+        };
+        let (prefix, s) = match s.split_once(':') {
+            Some((prefix, rest)) if ["tag", "name", "url", "host"].contains(&prefix) => {
+                (Some(prefix), rest)
+            }
+            _ => (None, s),
+        };
+        let (text, rest, quoted) = match s.strip_prefix('"') {
+            Some(quoted) => {
+                let (text, rest) = quoted.split_once('"').unwrap_or((quoted, ""));
+                (text, rest, true)
+            }
+            None => {
+                let end = s.find(char::is_whitespace).unwrap_or(s.len());
+                (&s[..end], &s[end..], false)
+            }
+        };
+        let token = Token {
+            negated,
+            prefix,
+            text: text.trim().to_string(),
+            quoted,
+        };
+        (token, rest)
+    }
+}
+
+/// An FTS5 string: `"…"`, with quotes doubled.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// A link matched by the search index, before ranking.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Match {
+    pub short_link: String,
+    pub url: String,
+    pub description: String,
+    /// The FTS5 `bm25` rank; more negative is more relevant.
+    pub rank: f64,
+    pub hits: i64,
+}
+
+/// Ranks every match for `query`, best first, by a combined [`score`]; with
+/// [`SortOrder::Popular`] by hits first. Ties go by name, so the order (and
+/// with it every page) is stable.
+pub fn rank(matches: &mut [Match], query: &SearchQuery, sort: SortOrder) {
+    let best_rank = matches.iter().map(|m| m.rank).fold(0.0, f64::min);
+    let max_hits = matches.iter().map(|m| m.hits).max().unwrap_or(0);
+    let mut scored: Vec<(f64, Match)> = matches
+        .iter()
+        .map(|m| (score(m, query, best_rank, max_hits), m.clone()))
+        .collect();
+    scored.sort_by(|(score_a, a), (score_b, b)| {
+        let by_hits = match sort {
+            SortOrder::Recent => std::cmp::Ordering::Equal,
+            SortOrder::Popular => b.hits.cmp(&a.hits),
+        };
+        by_hits
+            .then(score_b.total_cmp(score_a))
+            .then_with(|| a.short_link.cmp(&b.short_link))
+    });
+    for (slot, (_, m)) in matches.iter_mut().zip(scored) {
+        *slot = m;
+    }
+}
+
+/// Scores a match between 0 and 1, higher is better: how close it is to
+/// what `query` looks for, how relevant the index found it compared to the
+/// best match (`best_rank`), and how often it is used compared to the most
+/// used match.
+///
+/// Closeness is averaged over the free words and required terms. Free words
+/// count the name or the URL, whichever is closer, so `grafana.internal`
+/// ranks the link pointing there first; `url:` terms are compared with the
+/// URL's host and path, `host:` terms with the host alone.
+pub fn score(m: &Match, query: &SearchQuery, best_rank: f64, max_hits: i64) -> f64 {
+    let url = UrlParts::new(&m.url);
+    let (mut total, mut count) = (0.0, 0);
+    for (field, text) in query.targets() {
+        total += match field {
+            Field::Any => closeness(&m.short_link, text).max(url.closeness(text)),
+            Field::Name => closeness(&m.short_link, text),
+            Field::Url => url.closeness(text),
+            Field::Host => closeness(url.host, text),
+        };
+        count += 1;
+    }
+    let closeness = if count > 0 { total / count as f64 } else { 0.0 };
+    let relevance = if best_rank < 0.0 {
+        (m.rank / best_rank).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let popularity = if max_hits > 0 {
+        (m.hits.max(0) as f64).ln_1p() / (max_hits as f64).ln_1p()
+    } else {
+        0.0
+    };
+    CLOSENESS_WEIGHT * closeness + RELEVANCE_WEIGHT * relevance + POPULARITY_WEIGHT * popularity
+}
+
+/// 1 for the same text, down to 0 when every character differs.
+fn closeness(a: &str, b: &str) -> f64 {
+    let longest = a.chars().count().max(b.chars().count()).max(1);
+    let distance = TYPOS.between(a, b).unwrap_or(longest).min(longest);
+    1.0 - distance as f64 / longest as f64
+}
+
+/// The pieces of a link's URL a query can resemble.
+struct UrlParts<'a> {
+    host: &'a str,
+    /// The path without its leading and trailing slashes.
+    path: &'a str,
+    /// The host and path, e.g. `grafana.internal/d/cluster`.
+    host_path: &'a str,
+}
+
+impl<'a> UrlParts<'a> {
+    fn new(url: &'a str) -> Self {
+        let host_path = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let (authority, path) = host_path.split_once('/').unwrap_or((host_path, ""));
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+        let host = host.split(':').next().unwrap_or(host);
+        Self {
+            host: host.strip_prefix("www.").unwrap_or(host),
+            path,
+            host_path,
+        }
+    }
+
+    fn closeness(&self, text: &str) -> f64 {
+        [self.host, self.path, self.host_path]
+            .into_iter()
+            .map(|part| closeness(part, text))
+            .fold(0.0, f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(short_link: &str, rank: f64, hits: i64) -> Match {
+        with_url(short_link, "", rank, hits)
+    }
+
+    fn with_url(short_link: &str, url: &str, rank: f64, hits: i64) -> Match {
+        Match {
+            short_link: short_link.to_string(),
+            url: url.to_string(),
+            description: String::new(),
+            rank,
+            hits,
+        }
+    }
+
+    fn q(text: &str) -> SearchQuery {
+        SearchQuery::parse(text)
+    }
+
+    fn names(matches: &[Match]) -> Vec<&str> {
+        matches.iter().map(|m| m.short_link.as_str()).collect()
+    }
+
+    #[test]
+    fn test_rank() {
+        let mut matches = vec![
+            m("kubernetes-docs", -2.0, 0),
+            m("kubectl", -1.0, 50),
+            m("kubernetes", -1.5, 0),
+            m("kube", -1.0, 0),
+        ];
+        rank(&mut matches, &q("kbernetes"), SortOrder::Recent);
+        assert_eq!(
+            names(&matches),
+            ["kubernetes", "kubernetes-docs", "kubectl", "kube"]
+        );
+        rank(&mut matches, &q("kbernetes"), SortOrder::Popular);
+        assert_eq!(names(&matches)[0], "kubectl");
+
+        // Equal scores fall back to the name
+        let mut matches = vec![m("b", -1.0, 0), m("a", -1.0, 0)];
+        rank(&mut matches, &q("c"), SortOrder::Recent);
+        assert_eq!(names(&matches), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_score_bounds() {
+        assert_eq!(score(&m("wiki", -3.0, 10), &q("wiki"), -3.0, 10), 1.0);
+        // Nothing to be close to
+        assert_eq!(score(&m("", 0.0, 0), &q(""), 0.0, 0), 0.0);
+        let s = score(&m("wiki", -1.0, 0), &q("docs"), -3.0, 10);
+        assert!((0.0..1.0).contains(&s));
+    }
+
+    #[test]
+    fn test_rank_by_url() {
+        let mut matches = vec![
+            with_url("grafana-int", "https://grafana.example/internal", -1.0, 0),
+            with_url("dash", "https://grafana.internal/d/cluster", -1.0, 0),
+            with_url("grafana", "https://grafana.example", -1.0, 0),
+        ];
+        rank(&mut matches, &q("grafana.internal"), SortOrder::Recent);
+        assert_eq!(names(&matches)[0], "dash");
+        rank(&mut matches, &q("host:grafana"), SortOrder::Recent);
+        assert_eq!(names(&matches)[0], "grafana");
+        rank(
+            &mut matches,
+            &q("url:grafana.internal/d/cluster"),
+            SortOrder::Recent,
+        );
+        assert_eq!(names(&matches)[0], "dash");
+    }
+
+    #[test]
+    fn test_parse_tag_filters() {
+        assert_eq!(
+            SearchQuery::parse("tag:infra deploy tag:CI"),
+            SearchQuery {
+                text: "deploy".to_string(),
+                tags: vec!["ci".to_string(), "infra".to_string()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            SearchQuery::parse("  kube  ctl "),
+            SearchQuery {
+                text: "kube ctl".to_string(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(SearchQuery::parse("tag:"), SearchQuery::default());
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        let term = |field, text: &str| Term {
+            field,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            q(r#"dash host:grafana.internal -url:"/d/old" "build cluster" -staging -tag:old"#),
+            SearchQuery {
+                text: "dash".to_string(),
+                required: vec![
+                    term(Field::Host, "grafana.internal"),
+                    term(Field::Any, "build cluster"),
+                ],
+                excluded: vec![term(Field::Url, "/d/old"), term(Field::Any, "staging")],
+                excluded_tags: vec!["old".to_string()],
+                ..Default::default()
+            }
+        );
+        // Not fields or exclusions
+        assert_eq!(q("http://wiki a-b - x").text, "http://wiki a-b - x");
+        assert_eq!(
+            q(r#"name:"unclosed phrase"#).required,
+            [term(Field::Name, "unclosed phrase")]
+        );
+        assert_eq!(q(r#"name: "" url:"#), SearchQuery::default());
+    }
+
+    #[test]
+    fn test_fts_match() {
+        assert_eq!(q("wik").fts_match().as_deref(), Some(r#""wik""#));
+        assert_eq!(
+            q("kube").fts_match().as_deref(),
+            Some(r#"("kub" OR "ube")"#)
+        );
+        assert_eq!(
+            q(r#"name:gra host:intern -url:old -host:stage -"a b c""#)
+                .fts_match()
+                .as_deref(),
+            Some(r#"(short_link : "gra" AND url : "intern") NOT (url : "old" OR "a b c")"#)
+        );
+        // Too short to look up
+        assert_eq!(q("gr").fts_match(), None);
+        assert_eq!(q("name:go -staging").fts_match(), None);
+        assert_eq!(q("tag:infra").fts_match(), None);
+        assert_eq!(quote(r#"say "hi""#), r#""say ""hi""""#);
+    }
+
+    #[test]
+    fn test_admits() {
+        let query = q("host:grafana -url:/old name:g \"Build\"");
+        let admits = |name, url, description| query.admits(name, url, description);
+        assert!(admits(
+            "grafana",
+            "https://Grafana.internal/d",
+            "build dashboards"
+        ));
+        assert!(!admits(
+            "grafana",
+            "https://grafana.internal/old",
+            "build dashboards"
+        ));
+        assert!(!admits(
+            "grafana",
+            "https://docs.example/grafana",
+            "build dashboards"
+        ));
+        assert!(!admits(
+            "dash",
+            "https://grafana.internal/d",
+            "build dashboards"
+        ));
+        assert!(!admits(
+            "grafana",
+            "https://grafana.internal/d",
+            "dashboards"
+        ));
+        assert!(!admits("grafana", "not a url", "build"));
+
+        // Free words the index can't look up must appear as is
+        assert!(q("gr").admits("grafana", "https://grafana.internal", ""));
+        assert!(q("GR").admits("dash", "https://grafana.internal", ""));
+        assert!(!q("gr").admits("wiki", "https://wiki.example", ""));
+        assert!(q("wik").admits("docs", "https://docs.example", ""));
+    }
+}
@@ -1,6 +1,72 @@
+use crate::{
+    analytics::HitRecorder, auth::AuthSettings, chain, hosts::HostShortcuts, index::LinkIndex,
+    metrics::Metrics, reserved::ReservedNames, trash, url_policy::UrlPolicy,
+};
 use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+
+/// Runtime options that change how requests are handled.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// Redirect straight to the only existing link within one edit of a
+    /// missing short link, instead of showing suggestions.
+    pub auto_redirect_typos: bool,
+    pub auth: AuthSettings,
+    /// Names short links can't use.
+    pub reserved: ReservedNames,
+    /// What link and host shortcut targets may look like.
+    pub urls: UrlPolicy,
+    /// Links followed server-side when a link points at another one.
+    pub max_chain_depth: usize,
+    /// Days a deleted link stays in the trash; 0 keeps it until purged by hand.
+    pub trash_retention_days: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            auto_redirect_typos: false,
+            auth: AuthSettings::default(),
+            reserved: ReservedNames::default(),
+            urls: UrlPolicy::default(),
+            max_chain_depth: chain::DEFAULT_MAX_DEPTH,
+            trash_retention_days: trash::DEFAULT_RETENTION_DAYS,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
+    pub hits: HitRecorder,
+    /// Live links in memory, for redirects and searches.
+    pub index: LinkIndex,
+    /// Host shortcuts in memory, checked on every request.
+    pub hosts: HostShortcuts,
+    pub settings: Arc<Settings>,
+    pub metrics: Metrics,
+}
+
+impl AppState {
+    /// Builds the shared state and starts its background tasks.
+    pub fn new(pool: SqlitePool) -> Self {
+        let hits = HitRecorder::spawn(pool.clone());
+        let index = LinkIndex::new(pool.clone(), hits.clone());
+        tokio::spawn(index.clone().watch());
+        let hosts = HostShortcuts::new(pool.clone());
+        tokio::spawn(hosts.clone().watch());
+        Self {
+            pool,
+            hits,
+            index,
+            hosts,
+            settings: Arc::new(Settings::default()),
+            metrics: Metrics::default(),
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = Arc::new(settings);
+        self
+    }
 }
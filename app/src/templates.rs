@@ -1,14 +1,43 @@
-use crate::models::Link;
+use crate::{
+    chain::{Chain, ChainEnd},
+    models::{HostShortcut, Link, SortOrder, TrashedLink},
+};
 use askama::Template;
 
 #[derive(Template)]
 #[template(path = "index.html")]
-pub struct IndexTemplate;
+pub struct IndexTemplate {
+    /// The signed-in user, if any.
+    pub username: Option<String>,
+    /// Whether to offer the built-in sign in and sign out links.
+    pub local_auth: bool,
+    /// Whether the viewer may list the trash.
+    pub show_trash: bool,
+    /// Whether the viewer may export every link.
+    pub show_export: bool,
+    /// Search to show results for, from `/search`.
+    pub q: String,
+}
+
+#[derive(Template)]
+#[template(path = "opensearch.xml")]
+pub struct OpenSearchTemplate {
+    /// Where the app is reached, e.g. `http://to`.
+    pub base_url: String,
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+pub struct LoginTemplate {
+    pub error: Option<&'static str>,
+}
 
 #[derive(Template)]
 #[template(path = "create_link.html")]
 pub struct CreateLinkTemplate {
     pub short_link: String,
+    /// Existing links with a similar name, closest first.
+    pub suggestions: Vec<Link>,
 }
 
 #[derive(Template)]
@@ -16,8 +45,21 @@ pub struct CreateLinkTemplate {
 pub struct LinksListTemplate {
     pub links: Vec<Link>,
     pub page: u32,
+    pub pages: u64,
+    /// Links matching the query across all pages.
+    pub total: u64,
     pub has_next: bool,
     pub q: String,
+    pub sort: SortOrder,
+}
+
+/// The page a browser gets for a link whose chain loops or is too long.
+#[derive(Template)]
+#[template(path = "redirect_error.html")]
+pub struct RedirectErrorTemplate {
+    /// The link that was followed.
+    pub short_link: String,
+    pub message: String,
 }
 
 #[derive(Template)]
@@ -26,6 +68,50 @@ pub struct LinkRowTemplate {
     pub link: Link,
 }
 
+#[derive(Template)]
+#[template(path = "link_detail.html")]
+pub struct LinkDetailTemplate {
+    pub link: Link,
+    /// Where the link resolves to; `None` only if it vanished meanwhile.
+    pub chain: Option<Chain>,
+    pub max_depth: usize,
+}
+
+#[derive(Template)]
+#[template(path = "link_edit_row.html")]
+pub struct LinkEditRowTemplate {
+    pub link: Link,
+}
+
+#[derive(Template)]
+#[template(path = "trash_list.html")]
+pub struct TrashListTemplate {
+    pub links: Vec<TrashedLink>,
+    pub retention_days: u32,
+}
+
+/// A notice swapped into the dashboard's `#toast` out of band.
+#[derive(Template)]
+#[template(path = "toast.html")]
+pub struct ToastTemplate<'a> {
+    pub message: &'a str,
+    pub short_link: &'a str,
+    /// Whether to offer restoring `short_link` from the trash.
+    pub undo: bool,
+}
+
+#[derive(Template)]
+#[template(path = "hosts_list.html")]
+pub struct HostsListTemplate {
+    pub hosts: Vec<HostShortcut>,
+}
+
+#[derive(Template)]
+#[template(path = "host_row.html")]
+pub struct HostRowTemplate {
+    pub host: HostShortcut,
+}
+
 #[derive(Template)]
 #[template(path = "form_error.html")]
 pub struct FormErrorTemplate<'a> {
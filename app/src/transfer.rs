@@ -0,0 +1,532 @@
+use chrono::{DateTime, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use std::fmt::Write;
+
+use crate::{
+    chain,
+    models::{Link, NewLink, deserialize_tags, parse_tags},
+    queries,
+    state::Settings,
+};
+
+/// Serialization formats supported by import and export.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Csv,
+    /// Netscape bookmark file, as produced by every major browser.
+    Html,
+}
+
+impl Format {
+    /// Guesses the format from a file name's extension.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = std::path::Path::new(path).extension()?.to_str()?;
+        match ext.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "html" | "htm" => Some(Format::Html),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Html => "html",
+        }
+    }
+}
+
+/// What to do when an imported short link already exists.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the existing link and ignore the imported one.
+    #[default]
+    Skip,
+    /// Replace the existing link's URL.
+    Overwrite,
+    /// Import under the first free `name-N` variant.
+    Rename,
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    #[serde(default = "default_format")]
+    pub format: Format,
+}
+
+#[derive(Deserialize)]
+pub struct ImportParams {
+    #[serde(default = "default_format")]
+    pub format: Format,
+    #[serde(default)]
+    pub policy: ConflictPolicy,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_format() -> Format {
+    Format::Json
+}
+
+/// A link as read from an import file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ImportedLink {
+    pub short_link: String,
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, deserialize_with = "deserialize_tags")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub created_at: Option<NaiveDateTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+/// CSV can't hold nested lists, so tags are written space separated.
+#[derive(Serialize)]
+struct CsvRecord<'a> {
+    short_link: &'a str,
+    url: &'a str,
+    description: &'a str,
+    tags: String,
+    created_at: NaiveDateTime,
+    owner: &'a str,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub renamed: Vec<Renamed>,
+    pub skipped: Vec<String>,
+    pub invalid: Vec<Invalid>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Invalid {
+    pub short_link: String,
+    pub reason: String,
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.dry_run {
+            writeln!(f, "Dry run: no changes were saved.")?;
+        }
+        writeln!(f, "Created:     {}", self.created.len())?;
+        writeln!(f, "Overwritten: {}", self.overwritten.len())?;
+        writeln!(f, "Renamed:     {}", self.renamed.len())?;
+        for r in &self.renamed {
+            writeln!(f, "  {} -> {}", r.from, r.to)?;
+        }
+        writeln!(f, "Skipped:     {}", self.skipped.len())?;
+        for name in &self.skipped {
+            writeln!(f, "  {}", name)?;
+        }
+        writeln!(f, "Invalid:     {}", self.invalid.len())?;
+        for i in &self.invalid {
+            writeln!(f, "  {}: {}", i.short_link, i.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads every link, oldest first, for export.
+pub async fn fetch_all(pool: &SqlitePool) -> Result<Vec<Link>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "{} ORDER BY l.created_at, l.short_link",
+        Link::SELECT
+    ))
+    .fetch_all(pool)
+    .await
+}
+
+pub fn export(links: &[Link], format: Format) -> anyhow::Result<String> {
+    match format {
+        Format::Json => {
+            let records: Vec<ImportedLink> = links.iter().map(to_record).collect();
+            Ok(serde_json::to_string_pretty(&records)?)
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for link in links {
+                writer.serialize(CsvRecord {
+                    short_link: &link.short_link,
+                    url: &link.url,
+                    description: &link.description,
+                    tags: link.tags.join(" "),
+                    created_at: link.created_at,
+                    owner: link.owner.as_deref().unwrap_or_default(),
+                })?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        Format::Html => Ok(export_bookmarks(links)),
+    }
+}
+
+fn to_record(link: &Link) -> ImportedLink {
+    ImportedLink {
+        short_link: link.short_link.clone(),
+        url: link.url.clone(),
+        description: link.description.clone(),
+        tags: link.tags.clone(),
+        created_at: Some(link.created_at),
+        owner: link.owner.clone(),
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape_html(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+fn export_bookmarks(links: &[Link]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>to-links</TITLE>\n\
+         <H1>to-links</H1>\n\
+         <DL><p>\n",
+    );
+    for link in links {
+        let _ = writeln!(
+            out,
+            "    <DT><A HREF=\"{}\" ADD_DATE=\"{}\" SHORTCUTURL=\"{}\" TAGS=\"{}\">{}</A>",
+            escape_html(&link.url),
+            link.created_at.and_utc().timestamp(),
+            escape_html(&link.short_link),
+            escape_html(&link.tags.join(",")),
+            escape_html(&link.short_link),
+        );
+        if !link.description.is_empty() {
+            let _ = writeln!(out, "    <DD>{}", escape_html(&link.description));
+        }
+    }
+    out.push_str("</DL><p>\n");
+    out
+}
+
+pub fn parse(data: &str, format: Format) -> anyhow::Result<Vec<ImportedLink>> {
+    match format {
+        Format::Json => Ok(serde_json::from_str(data)?),
+        Format::Csv => {
+            let mut reader = csv::Reader::from_reader(data.as_bytes());
+            let links = reader
+                .deserialize()
+                .collect::<Result<Vec<ImportedLink>, _>>()?;
+            Ok(links)
+        }
+        Format::Html => Ok(parse_bookmarks(data)),
+    }
+}
+
+/// Extracts the value of `name="..."` from an HTML tag's attribute list.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let needle = format!("{}=\"", name.to_ascii_lowercase());
+    let start = lower
+        .match_indices(&needle)
+        .find(|(i, _)| *i == 0 || lower.as_bytes()[i - 1].is_ascii_whitespace())?
+        .0
+        + needle.len();
+    let end = start + tag[start..].find('"')?;
+    Some(unescape_html(&tag[start..end]))
+}
+
+/// Reads `<A HREF=...>` entries from a Netscape bookmark file. The short link
+/// is taken from the `SHORTCUTURL` keyword when present, otherwise from the
+/// bookmark title.
+fn parse_bookmarks(data: &str) -> Vec<ImportedLink> {
+    let lower = data.to_ascii_lowercase();
+    let mut links = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = lower[pos..].find("<a ") {
+        let start = pos + offset;
+        let Some(tag_end) = lower[start..].find('>').map(|i| start + i) else {
+            break;
+        };
+        let Some(close) = lower[tag_end..].find("</a>").map(|i| tag_end + i) else {
+            break;
+        };
+        let tag = &data[start + 3..tag_end];
+        let title = unescape_html(data[tag_end + 1..close].trim());
+        pos = close + 4;
+
+        // An optional <DD> right after the link holds its description.
+        let after = lower[pos..].trim_start();
+        let description = if after.starts_with("<dd>") {
+            let text_start = lower.len() - after.len() + 4;
+            let text_end = lower[text_start..]
+                .find('<')
+                .map_or(lower.len(), |i| text_start + i);
+            unescape_html(data[text_start..text_end].trim())
+        } else {
+            String::new()
+        };
+
+        let Some(url) = attribute(tag, "href") else {
+            continue;
+        };
+        let short_link = attribute(tag, "shortcuturl")
+            .filter(|s| !s.is_empty())
+            .unwrap_or(title);
+        let created_at = attribute(tag, "add_date")
+            .and_then(|s| s.parse::<i64>().ok())
+            .and_then(|ts| DateTime::from_timestamp(ts, 0))
+            .map(|dt| dt.naive_utc());
+        let tags = attribute(tag, "tags")
+            .map(|t| parse_tags(&t))
+            .unwrap_or_default();
+        links.push(ImportedLink {
+            short_link,
+            url,
+            description,
+            tags,
+            created_at,
+            owner: None,
+        });
+    }
+    links
+}
+
+async fn exists(tx: &mut Transaction<'_, Sqlite>, short_link: &str) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query("SELECT 1 FROM live_links WHERE short_link = ?")
+        .bind(short_link)
+        .fetch_optional(&mut **tx)
+        .await?
+        .is_some())
+}
+
+async fn insert(
+    tx: &mut Transaction<'_, Sqlite>,
+    short_link: &str,
+    link: &ImportedLink,
+    owner: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    queries::purge_trashed(tx, short_link).await?;
+    sqlx::query(
+        "INSERT INTO links (short_link, url, description, created_at, owner) \
+         VALUES (?, ?, ?, COALESCE(?, CURRENT_TIMESTAMP), ?)",
+    )
+    .bind(short_link)
+    .bind(&link.url)
+    .bind(&link.description)
+    .bind(link.created_at)
+    .bind(link.owner.as_deref().or(owner))
+    .execute(&mut **tx)
+    .await?;
+    queries::set_tags(tx, short_link, &link.tags).await
+}
+
+/// Imports `links` in a single transaction, applying `policy` to names that
+/// already exist. New links without a recorded owner are owned by `owner`;
+/// links breaking the rules in `settings` (reserved names, URL policy) are
+/// reported as invalid.
+/// With `dry_run` the transaction is rolled back, so the report describes
+/// exactly what a real import would do.
+pub async fn import(
+    pool: &SqlitePool,
+    links: Vec<ImportedLink>,
+    policy: ConflictPolicy,
+    dry_run: bool,
+    owner: Option<&str>,
+    settings: &Settings,
+) -> anyhow::Result<ImportReport> {
+    let mut report = ImportReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut tx = pool.begin().await?;
+
+    for mut link in links {
+        let candidate = NewLink {
+            short_link: link.short_link.clone(),
+            url: link.url.clone(),
+            description: link.description.clone(),
+            tags: link.tags.clone(),
+        };
+        match candidate
+            .validate()
+            .map_err(str::to_string)
+            .and_then(|_| settings.reserved.check(&link.short_link))
+            .and_then(|_| settings.urls.check_link(&link.short_link, &link.url))
+        {
+            Ok(url) => link.url = url,
+            Err(reason) => {
+                report.invalid.push(Invalid {
+                    short_link: link.short_link,
+                    reason,
+                });
+                continue;
+            }
+        }
+        let existed = exists(&mut tx, &link.short_link).await?;
+        let name = match policy {
+            _ if !existed => link.short_link.clone(),
+            ConflictPolicy::Skip => {
+                report.skipped.push(link.short_link);
+                continue;
+            }
+            ConflictPolicy::Overwrite => link.short_link.clone(),
+            ConflictPolicy::Rename => {
+                let mut n = 2;
+                loop {
+                    let name = format!("{}-{}", link.short_link, n);
+                    if !exists(&mut tx, &name).await? {
+                        break name;
+                    }
+                    n += 1;
+                }
+            }
+        };
+
+        // Checked against the links imported so far, so loops within the
+        // file are caught too.
+        let pending = chain::Pending {
+            short_link: &name,
+            url: &link.url,
+            replaces: None,
+        };
+        if let Err(reason) =
+            chain::check_pending(&mut *tx, &settings.urls, pending, settings.max_chain_depth)
+                .await?
+        {
+            report.invalid.push(Invalid {
+                short_link: link.short_link,
+                reason,
+            });
+            continue;
+        }
+
+        if name != link.short_link {
+            insert(&mut tx, &name, &link, owner).await?;
+            report.renamed.push(Renamed {
+                from: link.short_link,
+                to: name,
+            });
+        } else if existed {
+            sqlx::query("UPDATE links SET url = ?, description = ? WHERE short_link = ?")
+                .bind(&link.url)
+                .bind(&link.description)
+                .bind(&link.short_link)
+                .execute(&mut *tx)
+                .await?;
+            queries::set_tags(&mut tx, &link.short_link, &link.tags).await?;
+            report.overwritten.push(link.short_link);
+        } else {
+            insert(&mut tx, &link.short_link, &link, owner).await?;
+            report.created.push(link.short_link);
+        }
+    }
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(short_link: &str, url: &str) -> Link {
+        Link {
+            short_link: short_link.to_string(),
+            url: url.to_string(),
+            description: format!("About {}", short_link),
+            tags: vec!["infra".to_string(), "team-a".to_string()],
+            created_at: DateTime::from_timestamp(1_700_000_000, 0)
+                .unwrap()
+                .naive_utc(),
+            hits: 0,
+            last_accessed: None,
+            owner: Some("alice".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let links = vec![
+            link("kubernetes", "https://kubernetes.io"),
+            link("search", "https://example.com/?q=a&b=\"c\""),
+        ];
+        for format in [Format::Json, Format::Csv, Format::Html] {
+            let data = export(&links, format).unwrap();
+            let parsed = parse(&data, format).unwrap();
+            assert_eq!(parsed.len(), 2, "{:?}", format);
+            for (original, imported) in links.iter().zip(&parsed) {
+                assert_eq!(original.short_link, imported.short_link, "{:?}", format);
+                assert_eq!(original.url, imported.url, "{:?}", format);
+                assert_eq!(original.description, imported.description, "{:?}", format);
+                assert_eq!(original.tags, imported.tags, "{:?}", format);
+                assert_eq!(
+                    Some(original.created_at),
+                    imported.created_at,
+                    "{:?}",
+                    format
+                );
+                if format != Format::Html {
+                    assert_eq!(original.owner, imported.owner, "{:?}", format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_browser_bookmarks() {
+        let data = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<DL><p>
+    <DT><H3>Folder</H3>
+    <DL><p>
+        <DT><a href="https://grafana.example/" add_date="1700000000">grafana</a>
+        <DT><A HREF="https://ci.example/?a=1&amp;b=2" SHORTCUTURL="ci" TAGS="build,Infra">Build server</A>
+        <DD>Jenkins &amp; friends
+    </DL><p>
+</DL><p>"#;
+        let links = parse_bookmarks(data);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].short_link, "grafana");
+        assert!(links[0].created_at.is_some());
+        assert_eq!(links[1].short_link, "ci");
+        assert_eq!(links[1].url, "https://ci.example/?a=1&b=2");
+        assert_eq!(links[1].created_at, None);
+        assert_eq!(links[1].tags, vec!["build", "infra"]);
+        assert_eq!(links[1].description, "Jenkins & friends");
+        assert_eq!(links[0].description, "");
+    }
+}
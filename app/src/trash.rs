@@ -0,0 +1,28 @@
+use sqlx::SqlitePool;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::queries;
+
+/// Days a deleted link stays restorable unless configured otherwise.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// How often expired links are purged from the trash.
+pub const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Permanently removes links that have been in the trash for longer than
+/// `retention_days`, once at startup and then every [`PURGE_INTERVAL`].
+pub async fn run_purge(pool: SqlitePool, retention_days: u32) {
+    let mut ticker = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        ticker.tick().await;
+        match queries::purge_trash(&pool, retention_days).await {
+            Ok(0) => {}
+            Ok(purged) => info!(
+                "Purged {} link(s) deleted more than {} days ago",
+                purged, retention_days
+            ),
+            Err(e) => warn!("Failed to purge the trash: {}", e),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use percent_encoding::percent_decode_str;
+use std::{collections::BTreeSet, net::IpAddr};
+use url::Url;
+
+use crate::{hosts::normalize_host, url_template};
+
+/// Schemes accepted when nothing else is configured.
+pub const DEFAULT_SCHEMES: &[&str] = &["http", "https"];
+
+/// Rules for redirect targets: which schemes are allowed, and which hosts
+/// are the app itself (so a link can't redirect to itself).
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlPolicy {
+    schemes: BTreeSet<String>,
+    /// The app's own hostnames, without `.lan`.
+    own_hosts: Vec<String>,
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCHEMES.iter().copied(), ["to"])
+    }
+}
+
+impl UrlPolicy {
+    pub fn new<S, H>(schemes: S, own_hosts: H) -> Self
+    where
+        S: IntoIterator,
+        S::Item: AsRef<str>,
+        H: IntoIterator,
+        H::Item: AsRef<str>,
+    {
+        Self {
+            schemes: schemes
+                .into_iter()
+                .map(|s| s.as_ref().trim().trim_end_matches(':').to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            own_hosts: own_hosts
+                .into_iter()
+                .map(|h| normalize_host(h.as_ref()))
+                .filter(|h| !h.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Validates the target of `short_link` and returns it normalized.
+    pub fn check_link(&self, short_link: &str, url: &str) -> Result<String, String> {
+        let (normalized, parsed) = self.parse(url)?;
+        if self.is_own_host(&parsed) && points_to(&parsed, short_link) {
+            return Err(format!(
+                "URL points back to the short link '{}' itself",
+                short_link
+            ));
+        }
+        Ok(normalized)
+    }
+
+    /// Validates a host shortcut and returns its target normalized. The
+    /// shortcut can't take one of the app's own names.
+    pub fn check_host_shortcut(&self, host: &str, url: &str) -> Result<String, String> {
+        if self.is_own_hostname(host) {
+            return Err(format!("Host '{}' is the app's own address", host));
+        }
+        let (normalized, parsed) = self.parse(url)?;
+        let target = parsed.host_str().map(normalize_host);
+        if target.as_deref() == Some(&normalize_host(host)) {
+            return Err(format!("URL points back to the host '{}' itself", host));
+        }
+        Ok(normalized)
+    }
+
+    /// The short link path an `http(s)` URL on one of the app's own hosts
+    /// asks for (e.g. `gh/me` for `http://to/gh/me`), if any.
+    pub fn internal_path(&self, url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        if !matches!(url.scheme(), "http" | "https") || !self.is_own_host(&url) {
+            return None;
+        }
+        let segments: Vec<String> = url
+            .path_segments()?
+            .filter(|s| !s.is_empty())
+            .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+            .collect();
+        (!segments.is_empty()).then(|| segments.join("/"))
+    }
+
+    /// Whether `host` reaches the app itself: one of its own hostnames,
+    /// `localhost` or an IP address.
+    pub fn is_own_hostname(&self, host: &str) -> bool {
+        let host = normalize_host(host);
+        host == "localhost" || host.parse::<IpAddr>().is_ok() || self.own_hosts.contains(&host)
+    }
+
+    fn is_own_host(&self, url: &Url) -> bool {
+        url.host_str()
+            .is_some_and(|h| self.own_hosts.contains(&normalize_host(h)))
+    }
+
+    /// Parses `url` (a template, possibly with placeholders) and checks its
+    /// scheme. Returns the trimmed template with a lowercased scheme and host,
+    /// alongside a parsed sample of it.
+    fn parse(&self, url: &str) -> Result<(String, Url), String> {
+        let url = url.trim();
+        if url.is_empty() {
+            return Err("URL cannot be empty".to_string());
+        }
+        let sample = url_template::fill(url, "x")?;
+        let parsed = Url::parse(&sample).map_err(|e| match e {
+            url::ParseError::RelativeUrlWithoutBase => {
+                format!("URL '{}' must be absolute, e.g. https://{}", url, url)
+            }
+            e => format!("Invalid URL '{}': {}", url, e),
+        })?;
+
+        if !self.schemes.contains(parsed.scheme()) {
+            return Err(format!(
+                "URL scheme '{}' is not allowed (allowed: {})",
+                parsed.scheme(),
+                self.schemes.iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if matches!(parsed.scheme(), "http" | "https")
+            && parsed.host_str().is_none_or(str::is_empty)
+        {
+            return Err(format!("URL '{}' has no host", url));
+        }
+        Ok((normalize_prefix(url), parsed))
+    }
+}
+
+/// Whether `url`'s path starts with the segments of `short_link`, i.e. it
+/// would be resolved by the same link.
+fn points_to(url: &Url, short_link: &str) -> bool {
+    let path: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let link: Vec<&str> = short_link.split('/').collect();
+    path.len() >= link.len()
+        && path
+            .iter()
+            .zip(&link)
+            .all(|(segment, name)| percent_decode_str(segment).decode_utf8_lossy() == *name)
+}
+
+/// Lowercases the scheme and host of `url`, leaving the rest (and any
+/// placeholders) as written.
+fn normalize_prefix(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return url.to_string();
+    };
+    let scheme = scheme.to_ascii_lowercase();
+    let Some(rest) = rest.strip_prefix("//") else {
+        return format!("{}:{}", scheme, rest);
+    };
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(end);
+    let authority = match authority.rsplit_once('@') {
+        Some((userinfo, host)) => format!("{}@{}", userinfo, host.to_ascii_lowercase()),
+        None => authority.to_ascii_lowercase(),
+    };
+    format!("{}://{}{}", scheme, authority, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemes() {
+        let policy = UrlPolicy::default();
+        assert_eq!(
+            policy
+                .check_link("g", " HTTPS://Google.COM/Search?q=A ")
+                .unwrap(),
+            "https://google.com/Search?q=A"
+        );
+        assert_eq!(
+            policy
+                .check_link("jira", "https://Team.Atlassian.net/browse/{1}")
+                .unwrap(),
+            "https://team.atlassian.net/browse/{1}"
+        );
+        assert!(
+            policy
+                .check_link("x", "javascript:alert(1)")
+                .unwrap_err()
+                .contains("'javascript' is not allowed (allowed: http, https)")
+        );
+        assert!(
+            policy
+                .check_link("x", "example.com/page")
+                .unwrap_err()
+                .contains("must be absolute")
+        );
+        assert!(policy.check_link("x", "/relative").is_err());
+        assert!(policy.check_link("x", "http://").is_err());
+        assert!(policy.check_link("x", "").is_err());
+        assert!(policy.check_link("x", "ssh://pi@rpi").is_err());
+
+        let policy = UrlPolicy::new(["http", "https", "ssh:"], ["to"]);
+        assert_eq!(
+            policy.check_link("x", "ssh://Pi@RPI").unwrap(),
+            "ssh://Pi@rpi"
+        );
+    }
+
+    #[test]
+    fn test_self_loops() {
+        let policy = UrlPolicy::new(DEFAULT_SCHEMES, ["to", "go"]);
+        assert!(policy.check_link("wiki", "http://to/wiki").is_err());
+        assert!(
+            policy
+                .check_link("wiki", "http://to.lan:80/wiki/{*}")
+                .is_err()
+        );
+        assert!(policy.check_link("gh/me", "http://go/gh/me").is_err());
+        // Other links and pages on the same host are fine
+        assert!(policy.check_link("wiki", "http://to/docs").is_ok());
+        assert!(policy.check_link("wiki", "http://to/link").is_ok());
+        assert!(policy.check_link("gh/me", "http://to/gh").is_ok());
+        assert!(
+            policy
+                .check_link("wiki", "https://wiki.example/wiki")
+                .is_ok()
+        );
+
+        assert_eq!(
+            policy.internal_path("http://to.lan/gh/rust%20lang?x=1"),
+            Some("gh/rust lang".to_string())
+        );
+        assert_eq!(policy.internal_path("http://to/"), None);
+        assert_eq!(policy.internal_path("https://wiki.example/gh"), None);
+
+        assert!(
+            policy
+                .check_host_shortcut("maps", "http://maps.lan/")
+                .is_err()
+        );
+        assert!(
+            policy
+                .check_host_shortcut("maps", "https://maps.google.com")
+                .is_ok()
+        );
+        // The app's own names stay with the app
+        for host in ["to", "Go.lan", "localhost", "127.0.0.1", "[::1]"] {
+            assert!(
+                policy
+                    .check_host_shortcut(host, "https://maps.google.com")
+                    .is_err(),
+                "{:?}",
+                host
+            );
+        }
+    }
+}
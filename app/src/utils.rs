@@ -1,37 +1,215 @@
-pub fn levenshtein(a: &str, b: &str) -> usize {
-    let len_a = a.chars().count();
-    let len_b = b.chars().count();
-    if len_a == 0 {
-        return len_b;
+/// Strings up to this many characters are compared without allocating.
+const INLINE_LEN: usize = 64;
+
+/// How to measure the edit distance between two strings, e.g.
+/// `EditDistance::new().transpositions().max(2).between("gti", "git")`.
+///
+/// Only two rows of the distance matrix are kept (three with
+/// transpositions), on the stack for names up to [`INLINE_LEN`] characters.
+#[derive(Debug, Clone, Copy)]
+pub struct EditDistance {
+    max: usize,
+    transpositions: bool,
+    ignore_case: bool,
+}
+
+impl EditDistance {
+    /// Plain Levenshtein distance: insertions, deletions and substitutions.
+    pub const fn new() -> Self {
+        Self {
+            max: usize::MAX,
+            transpositions: false,
+            ignore_case: false,
+        }
     }
-    if len_b == 0 {
-        return len_a;
+
+    /// Gives up once the distance is known to exceed `max`.
+    pub const fn max(self, max: usize) -> Self {
+        Self { max, ..self }
     }
 
-    let mut dp = vec![vec![0; len_b + 1]; len_a + 1];
+    /// Counts swapping two adjacent characters as one edit (optimal string
+    /// alignment), so `gti` is one edit from `git` rather than two.
+    pub const fn transpositions(self) -> Self {
+        Self {
+            transpositions: true,
+            ..self
+        }
+    }
 
-    for i in 0..=len_a {
-        dp[i][0] = i;
+    /// Compares the strings as if both were lowercase.
+    pub const fn ignore_case(self) -> Self {
+        Self {
+            ignore_case: true,
+            ..self
+        }
     }
-    for j in 0..=len_b {
-        dp[0][j] = j;
+
+    /// The distance between `a` and `b`, or `None` if it exceeds the
+    /// [`max`](Self::max).
+    pub fn between(&self, a: &str, b: &str) -> Option<usize> {
+        if a.is_ascii() && b.is_ascii() {
+            let (a, b) = (a.as_bytes(), b.as_bytes());
+            return if self.ignore_case {
+                self.distance(a, b, u8::eq_ignore_ascii_case)
+            } else {
+                self.distance(a, b, u8::eq)
+            };
+        }
+        with_chars(a, self.ignore_case, |a| {
+            with_chars(b, self.ignore_case, |b| self.distance(a, b, char::eq))
+        })
     }
 
-    for (i, ca) in a.chars().enumerate() {
-        for (j, cb) in b.chars().enumerate() {
-            let cost = if ca == cb { 0 } else { 1 };
-            dp[i + 1][j + 1] = std::cmp::min(
-                dp[i][j + 1] + 1,
-                std::cmp::min(dp[i + 1][j] + 1, dp[i][j] + cost),
-            );
+    fn distance<T>(&self, a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> Option<usize> {
+        // A shared prefix or suffix never adds edits.
+        let prefix = a.iter().zip(b).take_while(|(x, y)| eq(x, y)).count();
+        let (a, b) = (&a[prefix..], &b[prefix..]);
+        let suffix = a
+            .iter()
+            .rev()
+            .zip(b.iter().rev())
+            .take_while(|(x, y)| eq(x, y))
+            .count();
+        let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+        // Rows run along the shorter string.
+        let (a, b) = if a.len() < b.len() { (b, a) } else { (a, b) };
+
+        if a.len() - b.len() > self.max {
+            return None;
+        }
+        if b.is_empty() {
+            return Some(a.len());
         }
+
+        let width = b.len() + 1;
+        let kept = if self.transpositions { 3 } else { 2 };
+        with_rows(kept * width, |rows| {
+            let row = |i: usize| (i % kept) * width;
+            for (j, cell) in rows[..width].iter_mut().enumerate() {
+                *cell = j;
+            }
+            for i in 1..=a.len() {
+                let (cur, prev) = (row(i), row(i - 1));
+                rows[cur] = i;
+                let mut row_min = i;
+                for j in 1..width {
+                    let cost = usize::from(!eq(&a[i - 1], &b[j - 1]));
+                    let mut d = (rows[prev + j] + 1)
+                        .min(rows[cur + j - 1] + 1)
+                        .min(rows[prev + j - 1] + cost);
+                    if self.transpositions
+                        && i > 1
+                        && j > 1
+                        && eq(&a[i - 1], &b[j - 2])
+                        && eq(&a[i - 2], &b[j - 1])
+                    {
+                        d = d.min(rows[row(i - 2) + j - 2] + 1);
+                    }
+                    rows[cur + j] = d;
+                    row_min = row_min.min(d);
+                }
+                // Rows never get cheaper, so this one's best is a lower bound.
+                if row_min > self.max {
+                    return None;
+                }
+            }
+            let d = rows[row(a.len()) + b.len()];
+            (d <= self.max).then_some(d)
+        })
+    }
+}
+
+impl Default for EditDistance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    EditDistance::new()
+        .between(a, b)
+        .expect("distance without a maximum")
+}
+
+/// Calls `f` with the characters of `s`, lowercased if `lowercase`.
+fn with_chars<R>(s: &str, lowercase: bool, f: impl FnOnce(&[char]) -> R) -> R {
+    let mut inline = ['\0'; INLINE_LEN];
+    let mut len = 0;
+    let mut spilled = Vec::new();
+    let mut push = |c: char| {
+        if len < INLINE_LEN {
+            inline[len] = c;
+        } else {
+            if spilled.is_empty() {
+                spilled.extend_from_slice(&inline);
+            }
+            spilled.push(c);
+        }
+        len += 1;
+    };
+    for c in s.chars() {
+        if lowercase {
+            c.to_lowercase().for_each(&mut push);
+        } else {
+            push(c);
+        }
+    }
+    if len <= INLINE_LEN {
+        f(&inline[..len])
+    } else {
+        f(&spilled)
+    }
+}
+
+/// Calls `f` with `len` zeroed cells.
+fn with_rows<R>(len: usize, f: impl FnOnce(&mut [usize]) -> R) -> R {
+    let mut inline = [0; 3 * (INLINE_LEN + 1)];
+    if len <= inline.len() {
+        f(&mut inline[..len])
+    } else {
+        f(&mut vec![0; len])
     }
-    dp[len_a][len_b]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    /// The full-matrix implementation this module used to have, with
+    /// optional transpositions.
+    fn reference(a: &str, b: &str, transpositions: bool) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        let mut dp = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for (i, row) in dp.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in dp[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                dp[i][j] = std::cmp::min(
+                    dp[i - 1][j] + 1,
+                    std::cmp::min(dp[i][j - 1] + 1, dp[i - 1][j - 1] + cost),
+                );
+                if transpositions && i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1]
+                {
+                    dp[i][j] = dp[i][j].min(dp[i - 2][j - 2] + 1);
+                }
+            }
+        }
+        dp[a.len()][b.len()]
+    }
+
+    /// Short strings over a small alphabet, so they share characters often.
+    fn name() -> impl Strategy<Value = String> {
+        "[abcABéÉ-]{0,12}"
+    }
 
     #[test]
     fn test_levenshtein_distance() {
@@ -42,4 +220,74 @@ mod tests {
         assert_eq!(levenshtein("", "abc"), 3);
         assert_eq!(levenshtein("abc", ""), 3);
     }
+
+    #[test]
+    fn test_edit_distance_options() {
+        let typos = EditDistance::new().transpositions();
+        assert_eq!(levenshtein("gti", "git"), 2);
+        assert_eq!(typos.between("gti", "git"), Some(1));
+        assert_eq!(typos.between("ca", "abc"), Some(3)); // no edits inside a swap
+        assert_eq!(EditDistance::new().between("Wiki", "wiki"), Some(1));
+        assert_eq!(
+            EditDistance::new().ignore_case().between("Wiki", "wiki"),
+            Some(0)
+        );
+        assert_eq!(
+            EditDistance::new().ignore_case().between("ÉTÉ", "été"),
+            Some(0)
+        );
+        assert_eq!(
+            EditDistance::new().max(2).between("kitten", "sitting"),
+            None
+        );
+        assert_eq!(
+            EditDistance::new().max(3).between("kitten", "sitting"),
+            Some(3)
+        );
+        assert_eq!(EditDistance::new().max(1).between("a", "abcdef"), None);
+
+        // Longer than fits on the stack
+        let long = "x".repeat(200);
+        assert_eq!(levenshtein(&long, &format!("{}y", long)), 1);
+        assert_eq!(levenshtein(&format!("é{}", long), &long), 1);
+    }
+
+    proptest! {
+        #[test]
+        fn matches_reference(a in name(), b in name()) {
+            prop_assert_eq!(levenshtein(&a, &b), reference(&a, &b, false));
+        }
+
+        #[test]
+        fn transpositions_match_reference(a in name(), b in name()) {
+            prop_assert_eq!(
+                EditDistance::new().transpositions().between(&a, &b),
+                Some(reference(&a, &b, true))
+            );
+        }
+
+        #[test]
+        fn max_cuts_off(a in name(), b in name(), max in 0usize..6, transpositions: bool) {
+            let expected = reference(&a, &b, transpositions);
+            let mut options = EditDistance::new().max(max);
+            if transpositions {
+                options = options.transpositions();
+            }
+            prop_assert_eq!(options.between(&a, &b), (expected <= max).then_some(expected));
+        }
+
+        #[test]
+        fn ignore_case_matches_lowercase(a in name(), b in name()) {
+            prop_assert_eq!(
+                EditDistance::new().ignore_case().transpositions().between(&a, &b),
+                Some(reference(&a.to_lowercase(), &b.to_lowercase(), true))
+            );
+        }
+
+        #[test]
+        fn symmetric(a in name(), b in name()) {
+            let typos = EditDistance::new().transpositions().ignore_case();
+            prop_assert_eq!(typos.between(&a, &b), typos.between(&b, &a));
+        }
+    }
 }
@@ -0,0 +1,255 @@
+use std::process::{Command, Output};
+
+fn run(db: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_app"))
+        .arg("--db")
+        .arg(db)
+        .args(args)
+        .env("RUST_LOG", "off")
+        // Keep the developer's own config file out of the tests
+        .env("HOME", std::path::Path::new(db).parent().unwrap())
+        .env(
+            "XDG_CONFIG_HOME",
+            std::path::Path::new(db).parent().unwrap(),
+        )
+        .output()
+        .expect("Failed to run binary")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_add_ls_show_rm() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("app.db").to_string_lossy().to_string();
+
+    let output = run(&db, &["add", "kubernetes", "https://kubernetes.io"]);
+    assert!(output.status.success());
+    assert!(
+        run(&db, &["add", "grafana", "https://grafana.example"])
+            .status
+            .success()
+    );
+
+    // Duplicates and invalid names are rejected
+    let output = run(&db, &["add", "kubernetes", "https://other.example"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+    assert!(
+        !run(&db, &["add", "bad name", "https://x.example"])
+            .status
+            .success()
+    );
+
+    let listing = stdout(&run(&db, &["ls"]));
+    assert!(listing.contains("kubernetes"));
+    assert!(listing.contains("https://grafana.example"));
+
+    // `search` uses the same fuzzy ranking as the dashboard
+    let results = stdout(&run(&db, &["search", "kbernetes"]));
+    assert!(results.lines().next().unwrap().starts_with("kubernetes"));
+    let results = stdout(&run(&db, &["search", "gr"]));
+    assert!(results.lines().next().unwrap().starts_with("grafana"));
+
+    let details = stdout(&run(&db, &["show", "grafana"]));
+    assert!(details.contains("URL:         https://grafana.example"));
+    assert!(details.contains("Last used:   never"));
+
+    assert!(run(&db, &["rm", "grafana"]).status.success());
+    assert!(!run(&db, &["rm", "grafana"]).status.success());
+    assert!(!run(&db, &["show", "grafana"]).status.success());
+
+    // Deleted links wait in the trash
+    assert!(stdout(&run(&db, &["trash", "ls"])).starts_with("grafana"));
+    assert!(run(&db, &["trash", "restore", "grafana"]).status.success());
+    assert!(run(&db, &["show", "grafana"]).status.success());
+    assert!(run(&db, &["rm", "grafana"]).status.success());
+    assert!(run(&db, &["trash", "purge", "grafana"]).status.success());
+    assert!(!run(&db, &["trash", "restore", "grafana"]).status.success());
+    assert_eq!(stdout(&run(&db, &["trash", "ls"])), "");
+}
+
+#[test]
+fn test_export_import_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("source.db").to_string_lossy().to_string();
+    let target = dir.path().join("target.db").to_string_lossy().to_string();
+    let file = dir.path().join("links.csv").to_string_lossy().to_string();
+
+    assert!(
+        run(&source, &["add", "wiki", "https://wiki.example"])
+            .status
+            .success()
+    );
+    assert!(run(&source, &["export", "-o", &file]).status.success());
+
+    let report = stdout(&run(&target, &["import", &file, "--dry-run"]));
+    assert!(report.contains("Dry run"));
+    assert!(stdout(&run(&target, &["ls"])).is_empty());
+
+    let report = stdout(&run(&target, &["import", &file]));
+    assert!(report.contains("Created:     1"));
+    assert!(stdout(&run(&target, &["ls"])).contains("wiki"));
+}
+
+#[test]
+fn test_user_management() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("app.db").to_string_lossy().to_string();
+
+    assert!(
+        run(&db, &["user", "add", "alice", "--password", "s3cret"])
+            .status
+            .success()
+    );
+    assert!(
+        run(
+            &db,
+            &["user", "add", "root", "--password", "toor", "--admin"]
+        )
+        .status
+        .success()
+    );
+    assert!(
+        !run(&db, &["user", "add", "alice", "--password", "again"])
+            .status
+            .success()
+    );
+    assert_eq!(stdout(&run(&db, &["user", "ls"])), "alice\nroot (admin)\n");
+
+    assert!(
+        run(
+            &db,
+            &["add", "wiki", "https://wiki.example", "--owner", "alice"]
+        )
+        .status
+        .success()
+    );
+    assert!(stdout(&run(&db, &["show", "wiki"])).contains("Owner:       alice"));
+
+    assert!(run(&db, &["user", "rm", "alice"]).status.success());
+    assert!(!run(&db, &["user", "rm", "alice"]).status.success());
+}
+
+#[test]
+fn test_config_layers() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("app.db").to_string_lossy().to_string();
+    let config = dir.path().join("to-links.toml");
+    std::fs::write(
+        &config,
+        "port = 8080\npool_size = 2\nlog_format = \"json\"\n\n[dns]\nhosts = [\"to\", \"maps\"]\n",
+    )
+    .unwrap();
+    let config = config.to_string_lossy().to_string();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_app"))
+        .args([
+            "--db",
+            &db,
+            "--config",
+            &config,
+            "--pool-size",
+            "3",
+            "config",
+            "check",
+        ])
+        .env("RUST_LOG", "off")
+        .env("TO_LINKS_PORT", "9090")
+        .env("TO_LINKS_POOL_SIZE", "4")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let effective = stdout(&output);
+    assert!(effective.contains(&format!("# Loaded from {}", config)));
+    // file < environment < flags
+    assert!(effective.contains("port = 9090"));
+    assert!(effective.contains("pool_size = 3"));
+    assert!(effective.contains("log_format = \"json\""));
+    assert!(effective.contains("\"maps\""));
+    assert!(effective.contains(&db));
+
+    // Mistakes are reported instead of ignored
+    let output = Command::new(env!("CARGO_BIN_EXE_app"))
+        .args(["--config", &config, "config", "check"])
+        .env("TO_LINKS_PROT", "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("TO_LINKS_PROT"));
+
+    std::fs::write(dir.path().join("bad.toml"), "port = \"high\"\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_app"))
+        .args([
+            "--config",
+            &dir.path().join("bad.toml").to_string_lossy(),
+            "config",
+            "check",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_serve_notifies_systemd() {
+    use std::os::unix::net::UnixDatagram;
+
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("app.db").to_string_lossy().to_string();
+    let socket_path = dir.path().join("notify.sock");
+    let socket = UnixDatagram::bind(&socket_path).unwrap();
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+        .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_app"))
+        .args(["--db", &db, "serve", "--bind", "127.0.0.1", "--port", "0"])
+        .env("RUST_LOG", "off")
+        .env("HOME", dir.path())
+        .env("XDG_CONFIG_HOME", dir.path())
+        .env("NOTIFY_SOCKET", &socket_path)
+        .env("WATCHDOG_USEC", "200000")
+        .spawn()
+        .unwrap();
+
+    let receive = || {
+        let mut buf = [0u8; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..len]).to_string()
+    };
+    let ready = receive();
+    let ping = receive();
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(ready, "READY=1");
+    assert_eq!(ping, "WATCHDOG=1");
+}
+
+#[test]
+fn test_allowed_schemes() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("app.db").to_string_lossy().to_string();
+
+    let output = run(&db, &["add", "pi", "ssh://pi@rpi"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("'ssh' is not allowed"));
+
+    let output = run(
+        &db,
+        &[
+            "--allow-scheme",
+            "https",
+            "--allow-scheme",
+            "ssh",
+            "add",
+            "pi",
+            "ssh://pi@rpi",
+        ],
+    );
+    assert!(output.status.success());
+}
@@ -0,0 +1,148 @@
+use app::dns::{DnsServer, DnsSettings};
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_MX: u16 = 15;
+
+async fn start(upstream: Option<SocketAddr>) -> SocketAddr {
+    let server = DnsServer::bind(DnsSettings {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        hosts: vec!["to".to_string(), "Maps.lan".to_string()],
+        addresses: vec!["192.168.1.2".parse().unwrap(), "fd00::2".parse().unwrap()],
+        upstream,
+    })
+    .await
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+    addr
+}
+
+fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.extend_from_slice(&[0, 0, qtype as u8, 0, 1]);
+    packet
+}
+
+async fn ask(server: SocketAddr, packet: &[u8]) -> Vec<u8> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.send_to(packet, server).await.unwrap();
+    let mut buf = [0u8; 512];
+    let (len, _) = tokio::time::timeout(
+        std::time::Duration::from_secs(2),
+        socket.recv_from(&mut buf),
+    )
+    .await
+    .expect("no DNS response")
+    .unwrap();
+    buf[..len].to_vec()
+}
+
+fn rcode(response: &[u8]) -> u8 {
+    response[3] & 0x0F
+}
+
+fn answer_count(response: &[u8]) -> u16 {
+    u16::from_be_bytes([response[6], response[7]])
+}
+
+#[tokio::test]
+async fn test_answers_shortcut_hosts() {
+    let server = start(None).await;
+
+    let response = ask(server, &query(1, "to", TYPE_A)).await;
+    assert_eq!(&response[..2], &[0, 1]);
+    assert_eq!(rcode(&response), 0);
+    assert_eq!(answer_count(&response), 1);
+    assert_eq!(&response[response.len() - 4..], &[192, 168, 1, 2]);
+
+    let response = ask(server, &query(2, "TO.lan", TYPE_AAAA)).await;
+    assert_eq!(answer_count(&response), 1);
+    assert_eq!(
+        &response[response.len() - 16..],
+        &"fd00::2".parse::<std::net::Ipv6Addr>().unwrap().octets()
+    );
+
+    // Configured with the suffix, still answered bare
+    let response = ask(server, &query(3, "maps", TYPE_A)).await;
+    assert_eq!(answer_count(&response), 1);
+
+    // Known name, no record of that type
+    let response = ask(server, &query(4, "to", TYPE_MX)).await;
+    assert_eq!(rcode(&response), 0);
+    assert_eq!(answer_count(&response), 0);
+}
+
+#[tokio::test]
+async fn test_answers_stored_host_shortcuts() {
+    let pool = app::setup_db("sqlite::memory:").await.unwrap();
+    let shortcut = app::models::NewHostShortcut {
+        host: "excalidraw".to_string(),
+        url: "https://excalidraw.com".to_string(),
+    };
+    app::queries::insert_host_shortcut(&pool, &shortcut, None)
+        .await
+        .unwrap();
+    let server = DnsServer::bind(DnsSettings {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        hosts: vec!["to".to_string()],
+        addresses: vec!["192.168.1.2".parse().unwrap()],
+        upstream: None,
+    })
+    .await
+    .unwrap()
+    .with_host_shortcuts(app::hosts::HostShortcuts::new(pool));
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    let response = ask(addr, &query(5, "excalidraw.lan", TYPE_A)).await;
+    assert_eq!(rcode(&response), 0);
+    assert_eq!(answer_count(&response), 1);
+    let response = ask(addr, &query(6, "maps", TYPE_A)).await;
+    assert_eq!(rcode(&response), 5);
+}
+
+#[tokio::test]
+async fn test_refuses_other_names_without_upstream() {
+    let server = start(None).await;
+    let response = ask(server, &query(7, "example.com", TYPE_A)).await;
+    assert_eq!(rcode(&response), 5);
+    assert_eq!(answer_count(&response), 0);
+
+    // Garbage with a valid header gets FORMERR
+    let response = ask(server, &[0, 9, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0, 5, b'a']).await;
+    assert_eq!(rcode(&response), 1);
+}
+
+#[tokio::test]
+async fn test_forwards_other_names_upstream() {
+    // A stand-in resolver that echoes the query back marked as a response
+    let upstream = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        loop {
+            let (len, peer) = upstream.recv_from(&mut buf).await.unwrap();
+            buf[2] |= 0x80;
+            buf[3] = 0x03;
+            upstream.send_to(&buf[..len], peer).await.unwrap();
+        }
+    });
+
+    let server = start(Some(upstream_addr)).await;
+    let response = ask(server, &query(42, "example.com", TYPE_A)).await;
+    assert_eq!(&response[..2], &42u16.to_be_bytes());
+    assert_eq!(rcode(&response), 3);
+
+    // Shortcut names are still answered locally
+    let response = ask(server, &query(43, "to", TYPE_A)).await;
+    assert_eq!(rcode(&response), 0);
+    assert_eq!(answer_count(&response), 1);
+}
@@ -1,17 +1,21 @@
 use app::{create_router, setup_db, state::AppState};
 use axum::{
     body::Body,
+    extract::connect_info::MockConnectInfo,
     http::{Request, StatusCode, header},
 };
 use http_body_util::BodyExt;
 use tower::ServiceExt;
 
 async fn setup_app() -> axum::Router {
+    create_router(setup_state().await)
+}
+
+async fn setup_state() -> AppState {
     let pool = setup_db("sqlite::memory:")
         .await
         .expect("Failed to create DB");
-    let state = AppState { pool };
-    create_router(state)
+    AppState::new(pool)
 }
 
 #[tokio::test]
@@ -118,6 +122,53 @@ async fn test_pagination() {
     assert!(body_str.contains("Page 2"));
 }
 
+#[tokio::test]
+async fn test_search_pagination_reaches_every_match() {
+    let app = setup_app().await;
+    for i in 1..=30 {
+        let (status, _) = send_json(
+            &app,
+            "POST",
+            "/api/links",
+            Some(serde_json::json!({
+                "short_link": format!("service-{:02}", i),
+                "url": format!("https://svc{}.example", i),
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+    create_link(&app, "short_link=unrelated&url=https://other.example").await;
+
+    let mut seen = std::collections::BTreeSet::new();
+    for page in 1..=4 {
+        let (_, body) = send_json(
+            &app,
+            "GET",
+            &format!("/api/links?q=servce&limit=8&page={}", page),
+            None,
+        )
+        .await;
+        assert_eq!(body["total"], 30);
+        assert_eq!(body["has_next"], page < 4);
+        let links = body["links"].as_array().unwrap();
+        assert_eq!(links.len(), if page < 4 { 8 } else { 6 });
+        for link in links {
+            assert!(seen.insert(link["short_link"].as_str().unwrap().to_string()));
+        }
+    }
+    assert_eq!(seen.len(), 30);
+
+    let (_, body) = send(&app, "GET", "/api/links?q=servce&limit=8&page=4", "").await;
+    assert!(body.contains("Page 4 of 4"));
+    assert!(body.contains("30 links"));
+    assert!(!body.contains("Next"));
+
+    let (_, body) = send_json(&app, "GET", "/api/links?limit=8&page=4", None).await;
+    assert_eq!(body["total"], 31);
+    assert_eq!(body["links"].as_array().unwrap().len(), 7);
+}
+
 #[tokio::test]
 async fn test_duplicate_error() {
     let app = setup_app().await;
@@ -175,3 +226,1533 @@ async fn test_validation_error() {
     let body_str = String::from_utf8(body.to_vec()).unwrap();
     assert!(body_str.contains("Invalid characters"));
 }
+
+async fn create_link(app: &axum::Router, body: &'static str) {
+    let request = Request::builder()
+        .uri("/api/links")
+        .method("POST")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+}
+
+async fn location_for(app: &axum::Router, uri: &str) -> Option<String> {
+    let request = Request::builder()
+        .uri(uri)
+        .method("GET")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    response
+        .headers()
+        .get(header::LOCATION)
+        .map(|v| v.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn test_parameterized_redirects() {
+    let app = setup_app().await;
+
+    // {1} -> https://jira.example/browse/{1}
+    create_link(
+        &app,
+        "short_link=jira&url=https://jira.example/browse/%7B1%7D",
+    )
+    .await;
+    // {*} -> https://github.com/{*}
+    create_link(&app, "short_link=gh&url=https://github.com/%7B*%7D").await;
+    create_link(
+        &app,
+        "short_link=gh/me&url=https://github.com/drjackild/%7B*%7D",
+    )
+    .await;
+    create_link(&app, "short_link=docs&url=https://docs.rs").await;
+
+    assert_eq!(
+        location_for(&app, "/jira/ABC-123").await.as_deref(),
+        Some("https://jira.example/browse/ABC-123")
+    );
+    assert_eq!(
+        location_for(&app, "/gh/rust-lang/rust").await.as_deref(),
+        Some("https://github.com/rust-lang/rust")
+    );
+    // The most specific prefix wins
+    assert_eq!(
+        location_for(&app, "/gh/me/to-links").await.as_deref(),
+        Some("https://github.com/drjackild/to-links")
+    );
+    // Links without placeholders get the suffix appended
+    assert_eq!(
+        location_for(&app, "/docs/serde/latest").await.as_deref(),
+        Some("https://docs.rs/serde/latest")
+    );
+    // Unknown prefixes still fall through to the create page
+    assert_eq!(location_for(&app, "/nope/abc").await, None);
+}
+
+#[tokio::test]
+async fn test_invalid_template_rejected() {
+    let app = setup_app().await;
+
+    let request = Request::builder()
+        .uri("/api/links")
+        .method("POST")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(
+            "short_link=jira&url=https://jira.example/%7Bid%7D",
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body_str = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body_str.contains("Invalid placeholder"));
+
+    // Request paths can't pick the host the link redirects to
+    let (status, body) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "any", "url": "https://{1}.example.com/"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Placeholders can only be used after the host in a URL template"
+    );
+}
+
+async fn send(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: &'static str,
+) -> (StatusCode, String) {
+    let request = Request::builder()
+        .uri(uri)
+        .method(method)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .header("HX-Request", "true")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn test_update_link() {
+    let app = setup_app().await;
+    create_link(&app, "short_link=grafana&url=https://grafana.old").await;
+    let (_, before) = send(&app, "GET", "/api/links/grafana", "").await;
+
+    // Change the URL only
+    let (status, body) = send(
+        &app,
+        "PATCH",
+        "/api/links/grafana",
+        "url=https://grafana.new",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("https://grafana.new"));
+    assert_eq!(
+        location_for(&app, "/grafana").await.as_deref(),
+        Some("https://grafana.new")
+    );
+
+    // Rename, keeping created_at
+    let (status, body) = send(
+        &app,
+        "PUT",
+        "/api/links/grafana",
+        "short_link=dash&url=https://grafana.new",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("id=\"link-dash\""));
+    let created_at = |html: &str| {
+        html.lines()
+            .map(str::trim)
+            .find(|l| l.starts_with("<td>20"))
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(created_at(&before), created_at(&body));
+    assert_eq!(location_for(&app, "/grafana").await, None);
+
+    // The FTS index follows the rename
+    let (_, body) = send(&app, "GET", "/api/links?q=dash", "").await;
+    assert!(body.contains("id=\"link-dash\""));
+}
+
+#[tokio::test]
+async fn test_update_link_errors() {
+    let app = setup_app().await;
+    create_link(&app, "short_link=one&url=https://one.com").await;
+    create_link(&app, "short_link=two&url=https://two.com").await;
+
+    let (status, body) = send(&app, "PUT", "/api/links/one", "short_link=two").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("already exists"));
+
+    let (status, body) = send(&app, "PUT", "/api/links/one", "short_link=bad name").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Invalid characters"));
+
+    let (_, body) = send(&app, "PUT", "/api/links/missing", "url=https://x.com").await;
+    assert!(body.contains("Link not found"));
+}
+
+#[tokio::test]
+async fn test_hit_counters_and_popular_sort() {
+    let state = setup_state().await;
+    let app = create_router(state.clone());
+    create_link(&app, "short_link=rarely&url=https://rarely.com").await;
+    create_link(&app, "short_link=often&url=https://often.com").await;
+    create_link(&app, "short_link=gh&url=https://github.com/%7B*%7D").await;
+
+    location_for(&app, "/rarely").await.unwrap();
+    for _ in 0..3 {
+        location_for(&app, "/often").await.unwrap();
+    }
+    location_for(&app, "/gh/rust-lang/rust").await.unwrap();
+    // Misses are not counted
+    assert_eq!(location_for(&app, "/missing").await, None);
+    state.hits.flush().await;
+
+    let (hits, daily): (i64, i64) = sqlx::query_as(
+        "SELECT s.hits, (SELECT SUM(hits) FROM link_hits_daily d WHERE d.short_link = s.short_link)
+         FROM link_stats s WHERE s.short_link = 'often'",
+    )
+    .fetch_one(&state.pool)
+    .await
+    .unwrap();
+    assert_eq!((hits, daily), (3, 3));
+
+    let (_, body) = send(&app, "GET", "/api/links?sort=popular", "").await;
+    let often = body.find("id=\"link-often\"").unwrap();
+    let rarely = body.find("id=\"link-rarely\"").unwrap();
+    let gh = body.find("id=\"link-gh\"").unwrap();
+    assert!(often < rarely && often < gh);
+    assert!(body.contains("<td>3</td>"));
+
+    // Stats follow a rename
+    send(&app, "PATCH", "/api/links/often", "short_link=frequent").await;
+    let (_, body) = send(&app, "GET", "/api/links/frequent", "").await;
+    assert!(body.contains("<td>3</td>"));
+}
+
+async fn send_json(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let mut builder = Request::builder()
+        .uri(uri)
+        .method(method)
+        .header(header::ACCEPT, "application/json");
+    let body = match body {
+        Some(value) => {
+            builder = builder.header(header::CONTENT_TYPE, "application/json");
+            Body::from(value.to_string())
+        }
+        None => Body::empty(),
+    };
+    let response = app
+        .clone()
+        .oneshot(builder.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value = if bytes.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+    (status, value)
+}
+
+#[tokio::test]
+async fn test_json_api_crud() {
+    let app = setup_app().await;
+
+    let (status, link) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "wiki", "url": "https://wiki.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(link["short_link"], "wiki");
+    assert_eq!(link["url"], "https://wiki.example");
+    assert_eq!(link["hits"], 0);
+
+    let (status, link) = send_json(&app, "GET", "/api/links/wiki", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(link["url"], "https://wiki.example");
+
+    let (status, link) = send_json(
+        &app,
+        "PATCH",
+        "/api/links/wiki",
+        Some(serde_json::json!({"url": "https://wiki.example/home"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(link["url"], "https://wiki.example/home");
+
+    let (status, page) = send_json(&app, "GET", "/api/links?q=wiki", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["links"][0]["short_link"], "wiki");
+    assert_eq!(page["has_next"], false);
+    assert_eq!(page["page"], 1);
+
+    let (status, _) = send_json(&app, "DELETE", "/api/links/wiki", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = send_json(&app, "GET", "/api/links/wiki", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_json_error_envelope() {
+    let app = setup_app().await;
+    let link = serde_json::json!({"short_link": "dup", "url": "https://dup.example"});
+    send_json(&app, "POST", "/api/links", Some(link.clone())).await;
+
+    let (status, body) = send_json(&app, "POST", "/api/links", Some(link)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["status"], 409);
+    assert_eq!(body["error"]["message"], "Short link 'dup' already exists");
+
+    let (status, body) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "bad name", "url": "https://x.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["message"], "Invalid characters in short link");
+
+    let (status, body) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"url": "https://x.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        body["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("short_link")
+    );
+
+    let (status, body) = send_json(&app, "DELETE", "/api/links/missing", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["message"], "Link not found");
+}
+
+async fn import(app: &axum::Router, query: &str, body: impl Into<String>) -> serde_json::Value {
+    let request = Request::builder()
+        .uri(format!("/api/import?{}", query))
+        .method("POST")
+        .body(Body::from(body.into()))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_import_conflict_policies() {
+    let app = setup_app().await;
+    create_link(&app, "short_link=wiki&url=https://old.wiki").await;
+
+    let csv = "short_link,url,created_at\n\
+               wiki,https://new.wiki,\n\
+               ci,https://ci.example,2024-01-02T03:04:05\n\
+               bad name,https://x.example,\n";
+
+    // Dry run reports without saving
+    let report = import(&app, "format=csv&policy=rename&dry_run=true", csv).await;
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["created"], serde_json::json!(["ci"]));
+    assert_eq!(report["renamed"][0]["to"], "wiki-2");
+    assert_eq!(report["invalid"][0]["short_link"], "bad name");
+    assert_eq!(location_for(&app, "/ci").await, None);
+
+    let report = import(&app, "format=csv&policy=skip", csv).await;
+    assert_eq!(report["skipped"], serde_json::json!(["wiki"]));
+    assert_eq!(
+        location_for(&app, "/wiki").await.as_deref(),
+        Some("https://old.wiki")
+    );
+    assert_eq!(
+        location_for(&app, "/ci").await.as_deref(),
+        Some("https://ci.example")
+    );
+
+    let report = import(&app, "format=csv&policy=overwrite", csv).await;
+    assert_eq!(report["overwritten"], serde_json::json!(["wiki", "ci"]));
+    assert_eq!(
+        location_for(&app, "/wiki").await.as_deref(),
+        Some("https://new.wiki")
+    );
+}
+
+#[tokio::test]
+async fn test_export_then_import_bookmarks() {
+    let source = setup_app().await;
+    create_link(&source, "short_link=kubernetes&url=https://kubernetes.io").await;
+    create_link(&source, "short_link=gh&url=https://github.com/%7B*%7D").await;
+
+    let request = Request::builder()
+        .uri("/api/export?format=html")
+        .body(Body::empty())
+        .unwrap();
+    let response = source.clone().oneshot(request).await.unwrap();
+    assert_eq!(
+        response.headers().get(header::CONTENT_DISPOSITION).unwrap(),
+        "attachment; filename=\"to-links.html\""
+    );
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let bookmarks = String::from_utf8(bytes.to_vec()).unwrap();
+    assert!(bookmarks.contains("SHORTCUTURL=\"kubernetes\""));
+
+    let target = setup_app().await;
+    let report = import(&target, "format=html", bookmarks).await;
+    assert_eq!(report["created"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        location_for(&target, "/gh/rust-lang").await.as_deref(),
+        Some("https://github.com/rust-lang")
+    );
+}
+
+#[tokio::test]
+async fn test_missing_link_suggestions() {
+    let app = setup_app().await;
+    create_link(&app, "short_link=kubernetes&url=https://kubernetes.io").await;
+    create_link(&app, "short_link=grafana&url=https://grafana.example").await;
+
+    let (status, body) = send(&app, "GET", "/kuberentes", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Did you mean"));
+    assert!(body.contains("href=\"/kubernetes\""));
+    assert!(!body.contains("grafana"));
+    // Auto-redirect is off by default
+    assert!(body.contains("name=\"short_link\" value=\"kuberentes\""));
+
+    let (_, body) = send(&app, "GET", "/zzzzzz", "").await;
+    assert!(!body.contains("Did you mean"));
+}
+
+#[tokio::test]
+async fn test_auto_redirect_single_typo() {
+    let state = setup_state().await.with_settings(app::state::Settings {
+        auto_redirect_typos: true,
+        ..Default::default()
+    });
+    let app = create_router(state);
+    create_link(&app, "short_link=kubernetes&url=https://kubernetes.io").await;
+    create_link(&app, "short_link=grafana&url=https://grafana.example").await;
+    create_link(&app, "short_link=grafana2&url=https://grafana2.example").await;
+
+    // One edit away from exactly one link
+    assert_eq!(
+        location_for(&app, "/kubernets").await.as_deref(),
+        Some("https://kubernetes.io")
+    );
+    // A swapped pair of letters or a different case is one typo too
+    assert_eq!(
+        location_for(&app, "/kuberentes").await.as_deref(),
+        Some("https://kubernetes.io")
+    );
+    assert_eq!(
+        location_for(&app, "/Kubernets").await.as_deref(),
+        Some("https://kubernetes.io")
+    );
+    // Further away: suggestions only
+    assert_eq!(location_for(&app, "/kubrnetis").await, None);
+    // Ambiguous: "grafana1" is one edit from both grafana and grafana2
+    assert_eq!(location_for(&app, "/grafana1").await, None);
+}
+
+#[tokio::test]
+async fn test_descriptions_and_tag_filters() {
+    let app = setup_app().await;
+    create_link(
+        &app,
+        "short_link=ci2&url=https://ci2.example&description=Second+build+cluster&tags=infra,+CI",
+    )
+    .await;
+    create_link(
+        &app,
+        "short_link=deploy&url=https://deploy.example&tags=infra",
+    )
+    .await;
+    create_link(
+        &app,
+        "short_link=deploy-docs&url=https://docs.example/deploy",
+    )
+    .await;
+
+    let (_, body) = send(&app, "GET", "/api/links/ci2", "").await;
+    assert!(body.contains("Second build cluster"));
+    assert!(body.contains("#ci"));
+    assert!(body.contains("#infra"));
+
+    // Descriptions are searchable
+    let (_, body) = send(&app, "GET", "/api/links?q=cluster", "").await;
+    assert!(body.contains("id=\"link-ci2\""));
+
+    // tag: filters, alone or combined with text
+    let (_, body) = send(&app, "GET", "/api/links?q=tag:infra", "").await;
+    assert!(body.contains("id=\"link-ci2\""));
+    assert!(body.contains("id=\"link-deploy\""));
+    assert!(!body.contains("id=\"link-deploy-docs\""));
+
+    let (_, body) = send(&app, "GET", "/api/links?q=tag:infra+deploy", "").await;
+    assert!(!body.contains("id=\"link-ci2\""));
+    assert!(body.contains("id=\"link-deploy\""));
+    assert!(!body.contains("id=\"link-deploy-docs\""));
+
+    let (_, body) = send(&app, "GET", "/api/links?q=tag:infra+tag:ci", "").await;
+    assert!(body.contains("id=\"link-ci2\""));
+    assert!(!body.contains("id=\"link-deploy\""));
+
+    // Updating tags through JSON
+    let (status, link) = send_json(
+        &app,
+        "PATCH",
+        "/api/links/deploy",
+        Some(serde_json::json!({"tags": ["Release"], "description": "Deploy UI"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(link["tags"], serde_json::json!(["release"]));
+    assert_eq!(link["description"], "Deploy UI");
+    let (_, body) = send(&app, "GET", "/api/links?q=tag:infra", "").await;
+    assert!(!body.contains("id=\"link-deploy\""));
+
+    let (status, body) = send(
+        &app,
+        "POST",
+        "/api/links",
+        "short_link=bad&url=https://x.example&tags=ok,no%21",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Invalid characters in tag"));
+}
+
+fn auth_app(state: AppState, mode: app::auth::AuthMode) -> axum::Router {
+    auth_app_from(state, mode, [127, 0, 0, 1])
+}
+
+/// Like [`auth_app`], with requests coming from `peer`.
+fn auth_app_from(state: AppState, mode: app::auth::AuthMode, peer: [u8; 4]) -> axum::Router {
+    create_router(state.with_settings(app::state::Settings {
+        auth: app::auth::AuthSettings {
+            mode,
+            admins: vec!["root".to_string()],
+            trusted_proxies: vec!["10.0.0.2".parse().unwrap()],
+            ..Default::default()
+        },
+        ..Default::default()
+    }))
+    .layer(MockConnectInfo(std::net::SocketAddr::from((peer, 40000))))
+}
+
+/// Sends a JSON request with extra headers, returning status, headers and body.
+async fn send_as(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<serde_json::Value>,
+) -> (StatusCode, axum::http::HeaderMap, serde_json::Value) {
+    let mut request = Request::builder()
+        .uri(uri)
+        .method(method)
+        .header(header::ACCEPT, "application/json")
+        .header(header::CONTENT_TYPE, "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let json = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+    (status, headers, json)
+}
+
+#[tokio::test]
+async fn test_proxy_auth_ownership() {
+    let app = auth_app(setup_state().await, app::auth::AuthMode::Proxy);
+    let alice = [("X-Remote-User", "alice")];
+    let bob = [("X-Remote-User", "bob")];
+    let root = [("X-Remote-User", "root")];
+    let wiki = serde_json::json!({"short_link": "wiki", "url": "https://wiki.example"});
+
+    let (status, _, _) = send_as(&app, "POST", "/api/links", &[], Some(wiki.clone())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _, link) = send_as(&app, "POST", "/api/links", &alice, Some(wiki)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(link["owner"], "alice");
+
+    // Anonymous users still resolve links
+    assert_eq!(
+        location_for(&app, "/wiki").await.as_deref(),
+        Some("https://wiki.example")
+    );
+
+    let patch = serde_json::json!({"url": "https://other.example"});
+    let (status, _, _) = send_as(&app, "PATCH", "/api/links/wiki", &bob, Some(patch.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send_as(&app, "DELETE", "/api/links/wiki", &bob, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send_as(
+        &app,
+        "POST",
+        "/api/import",
+        &bob,
+        Some(serde_json::json!([])),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, link) = send_as(&app, "PATCH", "/api/links/wiki", &alice, Some(patch)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(link["owner"], "alice");
+
+    let (status, _, _) = send_as(&app, "DELETE", "/api/links/wiki", &root, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // The trash only lists what the viewer may restore or purge
+    let (status, _, _) = send_as(&app, "GET", "/api/trash", &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, _, trash) = send_as(&app, "GET", "/api/trash", &bob, None).await;
+    assert_eq!(trash, serde_json::json!([]));
+    for viewer in [&alice, &root] {
+        let (_, _, trash) = send_as(&app, "GET", "/api/trash", viewer, None).await;
+        assert_eq!(trash[0]["short_link"], "wiki");
+    }
+    let (status, _, _) = send_as(&app, "DELETE", "/api/trash/wiki", &bob, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Exports include everyone's links
+    let (status, _, _) = send_as(&app, "GET", "/api/export", &[], None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = send_as(&app, "GET", "/api/export", &bob, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = send_as(&app, "GET", "/api/export", &root, None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_proxy_auth_trusts_only_proxies() {
+    let wiki = serde_json::json!({"short_link": "wiki", "url": "https://wiki.example"});
+    let root = [("X-Remote-User", "root")];
+
+    // A client reaching the app directly can't claim to be anyone
+    let direct = auth_app_from(
+        setup_state().await,
+        app::auth::AuthMode::Proxy,
+        [192, 168, 1, 50],
+    );
+    let (status, _, _) = send_as(&direct, "POST", "/api/links", &root, Some(wiki.clone())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // A configured proxy can
+    let proxied = auth_app_from(
+        setup_state().await,
+        app::auth::AuthMode::Proxy,
+        [10, 0, 0, 2],
+    );
+    let (status, _, link) = send_as(&proxied, "POST", "/api/links", &root, Some(wiki)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(link["owner"], "root");
+}
+
+#[tokio::test]
+async fn test_local_auth_sessions() {
+    let state = setup_state().await;
+    app::auth::create_user(&state.pool, "alice", "s3cret", false)
+        .await
+        .unwrap();
+    let app = auth_app(state, app::auth::AuthMode::Local);
+
+    let (status, _) = send(&app, "POST", "/login", "username=alice&password=wrong").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, headers, _) = send_as(&app, "GET", "/link", &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(headers.get(header::SET_COOKIE).is_none());
+
+    let request = Request::builder()
+        .uri("/login")
+        .method("POST")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from("username=alice&password=s3cret"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+    assert!(cookie.starts_with("to_links_session="));
+    let session = [(header::COOKIE.as_str(), cookie.as_str())];
+
+    let (status, _, link) = send_as(
+        &app,
+        "POST",
+        "/api/links",
+        &session,
+        Some(serde_json::json!({"short_link": "docs", "url": "https://docs.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(link["owner"], "alice");
+
+    let (status, headers, _) = send_as(&app, "POST", "/logout", &session, None).await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    assert!(
+        headers[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .contains("Max-Age=0")
+    );
+
+    // The old session no longer works
+    let (status, _, _) = send_as(&app, "DELETE", "/api/links/docs", &session, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+async fn location_for_host(app: &axum::Router, host: &str, uri: &str) -> Option<String> {
+    let request = Request::builder()
+        .uri(uri)
+        .method("GET")
+        .header(header::HOST, host)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    response
+        .headers()
+        .get(header::LOCATION)
+        .map(|v| v.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn test_host_shortcuts() {
+    let app = setup_app().await;
+    create_link(&app, "short_link=maps&url=https://path.example").await;
+
+    let (status, _) = send(
+        &app,
+        "POST",
+        "/api/hosts",
+        "host=Maps.lan&url=https://maps.google.com",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, host) = send_as(
+        &app,
+        "POST",
+        "/api/hosts",
+        &[],
+        Some(serde_json::json!({"host": "gh", "url": "https://github.com/{*}"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(host["host"], "gh");
+
+    // Duplicates are rejected after normalization
+    let (status, _, _) = send_as(
+        &app,
+        "POST",
+        "/api/hosts",
+        &[],
+        Some(serde_json::json!({"host": "MAPS", "url": "https://x.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The app's own names can't be taken over
+    for host in ["to", "TO.lan", "localhost", "127.0.0.1"] {
+        let (status, _, _) = send_as(
+            &app,
+            "POST",
+            "/api/hosts",
+            &[],
+            Some(serde_json::json!({"host": host, "url": "https://x.example"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", host);
+    }
+
+    for host in ["maps", "maps.lan", "MAPS.lan:80"] {
+        assert_eq!(
+            location_for_host(&app, host, "/").await.as_deref(),
+            Some("https://maps.google.com"),
+            "{}",
+            host
+        );
+    }
+    assert_eq!(
+        location_for_host(&app, "gh.lan", "/rust-lang/rust")
+            .await
+            .as_deref(),
+        Some("https://github.com/rust-lang/rust")
+    );
+
+    // Other hosts keep the regular short link behavior
+    assert_eq!(
+        location_for_host(&app, "to.lan", "/maps").await.as_deref(),
+        Some("https://path.example")
+    );
+
+    let (_, body) = send(&app, "GET", "/api/hosts", "").await;
+    assert!(body.contains("id=\"host-maps\""));
+    assert!(body.contains("id=\"host-gh\""));
+
+    let (status, _) = send(&app, "DELETE", "/api/hosts/maps", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(location_for_host(&app, "maps", "/").await, None);
+}
+
+#[tokio::test]
+async fn test_metrics() {
+    let state = setup_state().await;
+    let pool = state.pool.clone();
+    let index = state.index.clone();
+    let app = create_router(state);
+    send(
+        &app,
+        "POST",
+        "/api/links",
+        "short_link=grafana&url=https://grafana.lan",
+    )
+    .await;
+
+    let (status, _) = send(&app, "GET", "/grafana", "").await;
+    assert_eq!(status, StatusCode::SEE_OTHER);
+    let (status, _) = send(&app, "GET", "/nothing-here", "").await;
+    assert_eq!(status, StatusCode::OK);
+    // A loop stored before loops were refused
+    sqlx::raw_sql(
+        "INSERT INTO links (short_link, url) VALUES ('x', 'http://to/y'), ('y', 'http://to/x')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    index.reload().await.unwrap();
+    let (status, _) = send(&app, "GET", "/x", "").await;
+    assert_eq!(status, StatusCode::LOOP_DETECTED);
+    send(&app, "GET", "/api/links?q=graf", "").await;
+    let (status, _) = send_json(&app, "GET", "/api/links/nothing-here", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let request = Request::builder()
+        .uri("/metrics")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(
+        response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain; version=0.0.4")
+    );
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    // Requests are labelled by route, not by the raw path
+    assert!(body.contains(
+        "to_links_http_requests_total{method=\"GET\",route=\"/{short_link}\",status=\"303\"} 1\n"
+    ));
+    assert!(body.contains(
+        "to_links_http_requests_total{method=\"GET\",route=\"/{short_link}\",status=\"200\"} 1\n"
+    ));
+    assert!(!body.contains("nothing-here"));
+    assert!(body.contains(
+        "to_links_http_request_duration_seconds_count{method=\"POST\",route=\"/api/links\"} 1\n"
+    ));
+    // Errors count with their real status, not the 200 HTMX gets
+    assert!(body.contains(
+        "to_links_http_requests_total{method=\"GET\",route=\"/api/links/{short_link}\",status=\"404\"} 1\n"
+    ));
+    assert!(body.contains("to_links_redirects_total{outcome=\"hit\"} 1\n"));
+    assert!(body.contains("to_links_redirects_total{outcome=\"miss\"} 1\n"));
+    assert!(body.contains("to_links_redirects_total{outcome=\"loop\"} 1\n"));
+    assert!(body.contains("to_links_redirects_total{outcome=\"error\"} 0\n"));
+    assert!(body.contains("to_links_search_duration_seconds_count 1\n"));
+    assert!(body.contains("to_links_links 3\n"));
+    assert!(body.contains("to_links_db_pool_max_connections 5\n"));
+}
+
+#[tokio::test]
+async fn test_health_and_readiness() {
+    let state = setup_state().await;
+    let pool = state.pool.clone();
+    let app = create_router(state);
+
+    let (status, body) = send_json(&app, "GET", "/healthz", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+
+    let (status, body) = send_json(&app, "GET", "/readyz", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ok");
+    let checks: Vec<&str> = body["checks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(checks, ["database", "schema", "search_index"]);
+
+    // The endpoints can't be shadowed by short links
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "healthz", "url": "https://x.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // A link removed behind the search index's back leaves it inconsistent,
+    // which both the readiness and the startup check report
+    send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "wiki", "url": "https://wiki.example"})),
+    )
+    .await;
+    assert!(app::health::check_search_index(&pool).await.is_ok());
+    sqlx::raw_sql("DROP TRIGGER links_ad; DELETE FROM links WHERE short_link = 'wiki';")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(app::health::check_search_index(&pool).await.is_err());
+    let (status, body) = send_json(&app, "GET", "/readyz", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["checks"][2]["ok"], false);
+    assert_eq!(
+        body["checks"][2]["error"],
+        "Search index holds 1 links, expected 0"
+    );
+
+    // A database without a schema isn't ready, and probing it changes nothing
+    let pool = app::connect_db("sqlite::memory:").await.unwrap();
+    let app = create_router(AppState::new(pool.clone()));
+    let (status, body) = send_json(&app, "GET", "/readyz", None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["checks"][0]["ok"], true);
+    assert_eq!(body["checks"][1]["ok"], false);
+    assert!(body["checks"][1]["error"].is_string());
+    let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(tables, 0);
+}
+
+#[tokio::test]
+async fn test_reserved_names() {
+    let state = setup_state().await.with_settings(app::state::Settings {
+        reserved: app::reserved::ReservedNames::from_routes().with_extras(&["static".to_string()]),
+        ..Default::default()
+    });
+    let pool = state.pool.clone();
+    let app = create_router(state);
+
+    for name in [
+        "link",
+        "api",
+        "api/links",
+        "metrics",
+        "static",
+        "static/css",
+    ] {
+        let (status, body) = send_json(
+            &app,
+            "POST",
+            "/api/links",
+            Some(serde_json::json!({"short_link": name, "url": "https://x.example"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", name);
+        assert!(
+            body["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("reserved")
+        );
+    }
+
+    // Renaming into a reserved name is refused as well
+    create_link(&app, "short_link=links&url=https://links.example").await;
+    let (status, _) = send_json(
+        &app,
+        "PATCH",
+        "/api/links/links",
+        Some(serde_json::json!({"short_link": "login"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let report = import(
+        &app,
+        "format=csv",
+        "short_link,url\nlogout,https://x.example\nlogs,https://logs.example\n",
+    )
+    .await;
+    assert_eq!(report["created"], serde_json::json!(["logs"]));
+    assert_eq!(report["invalid"][0]["short_link"], "logout");
+
+    // Rows stored before a name became reserved are found for the startup report
+    sqlx::query("INSERT INTO links (short_link, url) VALUES ('readyz', 'https://old.example')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let conflicts = app::reserved::ReservedNames::default()
+        .find_conflicts(&pool)
+        .await
+        .unwrap();
+    assert_eq!(conflicts, ["readyz"]);
+}
+
+#[tokio::test]
+async fn test_url_validation() {
+    let app = setup_app().await;
+    let create = |name: &str, url: &str| {
+        send_json(
+            &app,
+            "POST",
+            "/api/links",
+            Some(serde_json::json!({"short_link": name, "url": url})),
+        )
+    };
+
+    for (url, message) in [
+        (
+            "javascript:alert(1)",
+            "URL scheme 'javascript' is not allowed (allowed: http, https)",
+        ),
+        (
+            "wiki.example/home",
+            "URL 'wiki.example/home' must be absolute, e.g. https://wiki.example/home",
+        ),
+        (
+            "http://to/wiki",
+            "URL points back to the short link 'wiki' itself",
+        ),
+    ] {
+        let (status, body) = create("wiki", url).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", url);
+        assert_eq!(body["error"]["message"], message);
+    }
+
+    // Targets are stored normalized
+    let (status, body) = create("wiki", " HTTPS://Wiki.Example/Home ").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["url"], "https://wiki.example/Home");
+
+    let (status, _) = send_json(
+        &app,
+        "PATCH",
+        "/api/links/wiki",
+        Some(serde_json::json!({"url": "data:text/html,hi"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/hosts",
+        Some(serde_json::json!({"host": "maps", "url": "http://maps.lan/"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let report = import(
+        &app,
+        "format=csv",
+        "short_link,url\nbookmarklet,javascript:void(0)\ndocs,https://docs.example\n",
+    )
+    .await;
+    assert_eq!(report["created"], serde_json::json!(["docs"]));
+    assert_eq!(report["invalid"][0]["short_link"], "bookmarklet");
+}
+
+#[tokio::test]
+async fn test_link_chains() {
+    let state = setup_state().await;
+    let pool = state.pool.clone();
+    let index = state.index.clone();
+    let app = create_router(state);
+    let create = |name: &str, url: &str| {
+        send_json(
+            &app,
+            "POST",
+            "/api/links",
+            Some(serde_json::json!({"short_link": name, "url": url})),
+        )
+    };
+
+    create_link(&app, "short_link=wiki&url=https://wiki.example").await;
+    create_link(&app, "short_link=docs&url=http://to.lan/wiki").await;
+    create_link(&app, "short_link=gh&url=https://github.com/{*}").await;
+    create_link(&app, "short_link=me&url=http://to/gh/me").await;
+
+    // Internal targets are resolved server-side
+    assert_eq!(
+        location_for(&app, "/docs").await.as_deref(),
+        Some("https://wiki.example")
+    );
+    assert_eq!(
+        location_for(&app, "/me").await.as_deref(),
+        Some("https://github.com/me")
+    );
+
+    // Saving a link that closes a loop is refused
+    let (status, _) = create("a", "http://to/b").await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, body) = create("b", "http://to/a").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Link would create a redirect loop: b → a → b"
+    );
+    let (status, body) = send_json(
+        &app,
+        "PATCH",
+        "/api/links/wiki",
+        Some(serde_json::json!({"url": "http://to/docs"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Link would create a redirect loop: wiki → docs → wiki"
+    );
+
+    let report = import(
+        &app,
+        "format=csv",
+        "short_link,url\np,http://to/q\nq,http://to/p\n",
+    )
+    .await;
+    assert_eq!(report["created"], serde_json::json!(["p"]));
+    assert_eq!(report["invalid"][0]["short_link"], "q");
+
+    // The detail view shows the chain
+    let (status, body) = send(&app, "GET", "/link/docs", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("Redirect Chain"));
+    assert!(body.contains("<a href=\"/link/wiki\"><strong>wiki</strong></a>"));
+    assert!(body.contains("Resolves to <strong>https://wiki.example</strong>"));
+    let (_, body) = send(&app, "GET", "/link/gh", "").await;
+    assert!(!body.contains("Redirect Chain"));
+
+    // Loops stored before the check existed are cut short
+    sqlx::raw_sql(
+        "INSERT INTO links (short_link, url) VALUES ('x', 'http://to/y'), ('y', 'http://to/x')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    index.reload().await.unwrap();
+    let (status, body) = send_json(&app, "GET", "/x", None).await;
+    assert_eq!(status, StatusCode::LOOP_DETECTED);
+    assert_eq!(body["error"]["message"], "Redirect loop: x → y → x");
+    let (_, body) = send(&app, "GET", "/link/x", "").await;
+    assert!(body.contains("redirects in a loop"));
+
+    // A browser following the link gets a full error page, not a fragment
+    let request = Request::builder()
+        .uri("/x")
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::LOOP_DETECTED);
+    assert!(!response.headers().contains_key("HX-Retarget"));
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("<!DOCTYPE html>"));
+    assert!(body.contains("Redirect loop: x → y → x"));
+    assert!(body.contains("<a href=\"/link/x\">"));
+}
+
+#[tokio::test]
+async fn test_chain_depth_limit() {
+    let app = create_router(setup_state().await.with_settings(app::state::Settings {
+        max_chain_depth: 2,
+        ..Default::default()
+    }));
+    create_link(&app, "short_link=c3&url=https://end.example").await;
+    create_link(&app, "short_link=c2&url=http://to/c3").await;
+    assert_eq!(
+        location_for(&app, "/c2").await.as_deref(),
+        Some("https://end.example")
+    );
+
+    let (status, body) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "c1", "url": "http://to/c2"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Link would create a redirect chain longer than 2 links: c1 → c2 → c3"
+    );
+
+    // Links leading to the saved one count too: `up` → `mid` sits at the limit
+    create_link(&app, "short_link=up&url=http://to/mid").await;
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "mid", "url": "https://end.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        location_for(&app, "/up").await.as_deref(),
+        Some("https://end.example")
+    );
+    let (status, body) = send_json(
+        &app,
+        "PATCH",
+        "/api/links/mid",
+        Some(serde_json::json!({"url": "http://to/c3"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Link would create a redirect chain longer than 2 links: up → mid → c3"
+    );
+}
+
+#[tokio::test]
+async fn test_trash() {
+    let state = setup_state().await;
+    let pool = state.pool.clone();
+    let app = create_router(state);
+    create_link(&app, "short_link=wiki&url=https://wiki.example&tags=docs").await;
+
+    // Deleting from the dashboard offers an undo
+    let (status, body) = send(&app, "DELETE", "/api/links/wiki", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("hx-swap-oob"));
+    assert!(body.contains("/api/trash/wiki/restore"));
+
+    // Trashed links don't resolve and aren't listed or searched
+    assert_eq!(location_for(&app, "/wiki").await, None);
+    let (_, body) = send_json(&app, "GET", "/api/links", None).await;
+    assert_eq!(body["links"], serde_json::json!([]));
+    let (_, body) = send_json(&app, "GET", "/api/links?q=wiki", None).await;
+    assert_eq!(body["links"], serde_json::json!([]));
+    let (_, body) = send_json(&app, "GET", "/api/trash", None).await;
+    assert_eq!(body[0]["short_link"], "wiki");
+    let (_, body) = send(&app, "GET", "/api/trash", "").await;
+    assert!(body.contains("Restore"));
+
+    let (status, body) = send_json(&app, "POST", "/api/trash/wiki/restore", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["tags"], serde_json::json!(["docs"]));
+    assert_eq!(
+        location_for(&app, "/wiki").await.as_deref(),
+        Some("https://wiki.example")
+    );
+    let (_, body) = send_json(&app, "GET", "/api/links?q=wiki", None).await;
+    assert_eq!(body["links"][0]["short_link"], "wiki");
+    let (status, _) = send_json(&app, "POST", "/api/trash/wiki/restore", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // A trashed name can be reused, which drops the trashed link
+    send_json(&app, "DELETE", "/api/links/wiki", None).await;
+    create_link(&app, "short_link=wiki&url=https://new-wiki.example").await;
+    let (_, body) = send_json(&app, "GET", "/api/trash", None).await;
+    assert_eq!(body, serde_json::json!([]));
+
+    // Purging one link, or everything past the retention period
+    send_json(&app, "DELETE", "/api/links/wiki", None).await;
+    let (status, _) = send_json(&app, "DELETE", "/api/trash/wiki", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send_json(&app, "POST", "/api/trash/wiki/restore", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    create_link(&app, "short_link=old&url=https://old.example").await;
+    create_link(&app, "short_link=recent&url=https://recent.example").await;
+    send_json(&app, "DELETE", "/api/links/old", None).await;
+    send_json(&app, "DELETE", "/api/links/recent", None).await;
+    sqlx::query(
+        "UPDATE links SET deleted_at = datetime('now', '-31 days') WHERE short_link = 'old'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    assert_eq!(app::queries::purge_trash(&pool, 30).await.unwrap(), 1);
+    let (_, body) = send_json(&app, "GET", "/api/trash", None).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["short_link"], "recent");
+
+    // Restoring is refused when it would close a loop
+    create_link(&app, "short_link=a&url=http://to/b").await;
+    send_json(&app, "DELETE", "/api/links/a", None).await;
+    create_link(&app, "short_link=b&url=http://to/a").await;
+    let (status, body) = send_json(&app, "POST", "/api/trash/a/restore", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        body["error"]["message"],
+        "Link would create a redirect loop: a → b → a"
+    );
+}
+
+#[tokio::test]
+async fn test_link_index_follows_writes() {
+    let state = setup_state().await;
+    let pool = state.pool.clone();
+    let index = state.index.clone();
+    let app = create_router(state);
+    create_link(&app, "short_link=git&url=https://git.example").await;
+
+    // The first lookup loads the index; later writes keep it current
+    assert_eq!(
+        location_for(&app, "/git").await.as_deref(),
+        Some("https://git.example")
+    );
+    create_link(&app, "short_link=wiki&url=https://wiki.example").await;
+    assert_eq!(
+        location_for(&app, "/wiki").await.as_deref(),
+        Some("https://wiki.example")
+    );
+    send_json(
+        &app,
+        "PATCH",
+        "/api/links/wiki",
+        Some(serde_json::json!({"short_link": "docs", "url": "https://docs.example"})),
+    )
+    .await;
+    assert_eq!(location_for(&app, "/wiki").await, None);
+    assert_eq!(
+        location_for(&app, "/docs").await.as_deref(),
+        Some("https://docs.example")
+    );
+    send_json(&app, "DELETE", "/api/links/docs", None).await;
+    assert_eq!(location_for(&app, "/docs").await, None);
+    send_json(&app, "POST", "/api/trash/docs/restore", None).await;
+    assert!(location_for(&app, "/docs").await.is_some());
+    import(
+        &app,
+        "",
+        r#"[{"short_link": "jira", "url": "https://jira.example"}]"#,
+    )
+    .await;
+    assert!(location_for(&app, "/jira").await.is_some());
+
+    // Searches come from memory too, including names the trigrams miss
+    let (_, body) = send_json(&app, "GET", "/api/links?q=gti", None).await;
+    assert_eq!(body["links"][0]["short_link"], "git");
+    assert_eq!(body["total"], 1);
+    location_for(&app, "/git").await;
+    let (_, body) = send_json(&app, "GET", "/api/links?sort=popular", None).await;
+    assert_eq!(body["links"][0]["short_link"], "git");
+    assert_eq!(body["links"][0]["hits"], 2);
+
+    // The app's own writes leave nothing for the watcher to reload
+    assert!(index.is_current().await.unwrap());
+
+    // Changes made elsewhere show up once the index catches up, keeping the
+    // hits counted so far
+    sqlx::query(
+        "INSERT INTO links (short_link, url) VALUES ('grafana', 'https://grafana.example')",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    assert_eq!(location_for(&app, "/grafana").await, None);
+    assert!(!index.is_current().await.unwrap());
+    index.catch_up().await;
+    assert!(index.is_current().await.unwrap());
+    assert!(location_for(&app, "/grafana").await.is_some());
+    let (_, body) = send_json(&app, "GET", "/api/links/git", None).await;
+    assert_eq!(body["hits"], 2);
+
+    // Without the changes to replay, it reloads instead
+    sqlx::raw_sql(
+        "UPDATE links SET url = 'https://grafana.internal' WHERE short_link = 'grafana';
+         DELETE FROM link_changes;",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    index.catch_up().await;
+    assert_eq!(
+        location_for(&app, "/grafana").await.as_deref(),
+        Some("https://grafana.internal")
+    );
+    assert!(index.is_current().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_search_query_syntax() {
+    let state = setup_state().await;
+    let pool = state.pool.clone();
+    let app = create_router(state);
+    create_link(
+        &app,
+        "short_link=dash&url=https://grafana.internal/d/cluster&description=Cluster+dashboards&tags=infra",
+    )
+    .await;
+    create_link(
+        &app,
+        "short_link=grafana&url=https://grafana.example&tags=old",
+    )
+    .await;
+    create_link(
+        &app,
+        "short_link=staging-dash&url=https://grafana.staging.internal/d/cluster",
+    )
+    .await;
+    create_link(
+        &app,
+        "short_link=prom&url=https://prom.example/graph/grafana.internal",
+    )
+    .await;
+
+    // The in-memory index and SQLite agree on what matches, and with the
+    // same bm25 relevance on the order too
+    let search = async |q: &str| {
+        let (_, body) =
+            send_json(&app, "GET", &format!("/api/links?q={}", urlencode(q)), None).await;
+        let names: Vec<String> = body["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l["short_link"].as_str().unwrap().to_string())
+            .collect();
+        let params = app::models::SearchParams {
+            q: Some(q.to_string()),
+            page: 1,
+            limit: 20,
+            sort: Default::default(),
+        };
+        let (links, total) = app::queries::search_links(&pool, &params).await.unwrap();
+        let from_db: Vec<String> = links.into_iter().map(|l| l.short_link).collect();
+        assert_eq!(total as usize, from_db.len());
+        assert_eq!(names, from_db, "{}", q);
+        names
+    };
+
+    // URLs rank by their host and path, not just the name
+    assert_eq!(search("grafana.internal").await[0], "dash");
+    assert_eq!(search("host:grafana.internal").await, ["dash"]);
+    assert_eq!(search("url:grafana.internal").await, ["dash", "prom"]);
+    let mut found = search("host:grafana -host:staging").await;
+    found.sort();
+    assert_eq!(found, ["dash", "grafana"]);
+    assert_eq!(search("name:dash").await, ["dash", "staging-dash"]);
+    assert_eq!(search("\"cluster dash\"").await, ["dash"]);
+    assert_eq!(search("name:da -tag:infra").await, ["staging-dash"]);
+    let mut found = search("-tag:old -prom").await;
+    found.sort();
+    assert_eq!(found, ["dash", "staging-dash"]);
+}
+
+fn urlencode(text: &str) -> String {
+    percent_encoding::utf8_percent_encode(text, percent_encoding::NON_ALPHANUMERIC).to_string()
+}
+
+#[tokio::test]
+async fn test_opensearch() {
+    let app = setup_app().await;
+    create_link(
+        &app,
+        "short_link=wiki&url=https://wiki.example&description=Team+wiki",
+    )
+    .await;
+    create_link(&app, "short_link=wiki-old&url=https://old.wiki.example").await;
+    let get = async |uri: &str| {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::HOST, "to")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let content_type = response.headers()[header::CONTENT_TYPE].clone();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (content_type, String::from_utf8(body.to_vec()).unwrap())
+    };
+
+    let (content_type, xml) = get("/opensearch.xml").await;
+    assert_eq!(content_type, "application/opensearchdescription+xml");
+    assert!(xml.contains(r#"template="http://to/search?q={searchTerms}""#));
+    assert!(xml.contains(r#"template="http://to/api/suggest?q={searchTerms}""#));
+
+    let (content_type, json) = get("/api/suggest?q=wik").await;
+    assert_eq!(content_type, "application/x-suggestions+json");
+    let suggestions: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(
+        suggestions,
+        serde_json::json!([
+            "wik",
+            ["wiki", "wiki-old"],
+            ["Team wiki", "https://old.wiki.example"],
+            ["http://to/wiki", "http://to/wiki-old"]
+        ])
+    );
+    let (_, json) = get("/api/suggest?q=").await;
+    assert_eq!(json, r#"["",[],[],[]]"#);
+
+    // Exact names redirect; anything else shows the ranked results
+    assert_eq!(
+        location_for(&app, "/search?q=wiki").await.as_deref(),
+        Some("https://wiki.example")
+    );
+    assert_eq!(location_for(&app, "/search?q=wik").await, None);
+    let (_, html) = get("/search?q=wik").await;
+    assert!(html.contains(r#"value="wik""#));
+    assert!(html.contains(r#"rel="search""#));
+    let (_, html) = get("/search?q=wiki+old").await;
+    assert!(html.contains(r#"value="wiki old""#));
+
+    // `search` is taken by the route now
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        "/api/links",
+        Some(serde_json::json!({"short_link": "search", "url": "https://search.example"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,127 @@
+use app::{connect_db, migrations, setup_db};
+use sqlx::SqlitePool;
+
+/// The schema `setup_db` created before versioned migrations existed.
+const UNVERSIONED_SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS links (
+        short_link TEXT PRIMARY KEY NOT NULL,
+        url TEXT NOT NULL,
+        created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS links_fts USING fts5(short_link, url, content='links', content_rowid='rowid', tokenize='trigram');
+    CREATE TRIGGER IF NOT EXISTS links_ai AFTER INSERT ON links BEGIN
+        INSERT INTO links_fts(rowid, short_link, url) VALUES (new.rowid, new.short_link, new.url);
+    END;
+    CREATE TRIGGER IF NOT EXISTS links_ad AFTER DELETE ON links BEGIN
+        INSERT INTO links_fts(links_fts, rowid, short_link, url) VALUES('delete', old.rowid, old.short_link, old.url);
+    END;
+    CREATE TRIGGER IF NOT EXISTS links_au AFTER UPDATE ON links BEGIN
+        INSERT INTO links_fts(links_fts, rowid, short_link, url) VALUES('delete', old.rowid, old.short_link, old.url);
+        INSERT INTO links_fts(rowid, short_link, url) VALUES (new.rowid, new.short_link, new.url);
+    END;
+"#;
+
+async fn unversioned_db(dir: &tempfile::TempDir) -> String {
+    let path = dir.path().join("app.db").to_string_lossy().to_string();
+    let pool = connect_db(&path).await.unwrap();
+    sqlx::raw_sql(UNVERSIONED_SCHEMA)
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO links (short_link, url, created_at) VALUES ('kubernetes', 'https://kubernetes.io', '2024-01-02 03:04:05')")
+        .execute(&pool)
+        .await
+        .unwrap();
+    pool.close().await;
+    path
+}
+
+async fn table_exists(pool: &SqlitePool, name: &str) -> bool {
+    sqlx::query("SELECT 1 FROM sqlite_master WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+#[tokio::test]
+async fn test_fresh_database_is_at_latest_version() {
+    let pool = setup_db("sqlite::memory:").await.unwrap();
+    assert_eq!(
+        migrations::current_version(&pool).await.unwrap(),
+        migrations::latest_version()
+    );
+    assert!(table_exists(&pool, "link_stats").await);
+}
+
+#[tokio::test]
+async fn test_upgrade_unversioned_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = unversioned_db(&dir).await;
+
+    let pool = setup_db(&path).await.unwrap();
+    assert_eq!(
+        migrations::current_version(&pool).await.unwrap(),
+        migrations::latest_version()
+    );
+
+    // Existing rows survive, and the FTS index still finds them
+    let (url, created_at): (String, String) =
+        sqlx::query_as("SELECT url, created_at FROM links WHERE short_link = 'kubernetes'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(url, "https://kubernetes.io");
+    assert_eq!(created_at, "2024-01-02 03:04:05");
+    let (matches,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM links_fts WHERE links_fts MATCH 'kube'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(matches, 1);
+
+    for migration in migrations::MIGRATIONS {
+        let (name,): (String,) =
+            sqlx::query_as("SELECT name FROM schema_version WHERE version = ?")
+                .bind(migration.version)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(name, migration.name);
+    }
+    assert!(table_exists(&pool, "link_stats").await);
+    assert!(table_exists(&pool, "link_hits_daily").await);
+    assert!(table_exists(&pool, "link_tags").await);
+
+    // Rows from before the description column get an empty one
+    let (description,): (String,) =
+        sqlx::query_as("SELECT description FROM links WHERE short_link = 'kubernetes'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(description, "");
+}
+
+#[tokio::test]
+async fn test_migrate_is_idempotent() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = unversioned_db(&dir).await;
+
+    let pool = connect_db(&path).await.unwrap();
+    let applied = migrations::migrate(&pool).await.unwrap();
+    assert_eq!(applied.len(), migrations::MIGRATIONS.len());
+    let applied = migrations::migrate(&pool).await.unwrap();
+    assert!(applied.is_empty());
+}
+
+#[tokio::test]
+async fn test_refuses_newer_schema() {
+    let pool = setup_db("sqlite::memory:").await.unwrap();
+    sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, 'from the future')")
+        .bind(migrations::latest_version() + 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(migrations::migrate(&pool).await.is_err());
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Renders `kind` for a database holding two host shortcuts and compares it
+/// with `tests/snapshots/<file>`. Run with `UPDATE_SNAPSHOTS=1` to accept
+/// intended changes.
+fn check_snapshot(kind: &str, extra_args: &[&str], file: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("app.db").to_string_lossy().to_string();
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_app"))
+            .arg("--db")
+            .arg(&db)
+            .args(args)
+            .env("RUST_LOG", "off")
+            .env("HOME", dir.path())
+            .env("XDG_CONFIG_HOME", dir.path())
+            .output()
+            .expect("Failed to run binary");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    };
+    run(&["host", "add", "maps", "https://maps.google.com"]);
+    run(&["host", "add", "excalidraw.lan", "https://excalidraw.com"]);
+
+    let mut args = vec![
+        "render-config",
+        kind,
+        "--address",
+        "192.168.1.78",
+        "--exec",
+        "/home/pi/to-links/to-links-app",
+        "--user",
+        "pi",
+    ];
+    args.extend_from_slice(extra_args);
+    let rendered = run(&args).replace(&db, "/home/pi/.local/share/to-links/app.db");
+
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(file);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, &rendered).unwrap();
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Missing snapshot {}", path.display()));
+    assert_eq!(rendered, expected, "{} differs from its snapshot", file);
+}
+
+#[test]
+fn test_render_nginx() {
+    check_snapshot("nginx", &["--port", "3000"], "nginx.conf");
+}
+
+#[test]
+fn test_render_nginx_proxy_auth() {
+    check_snapshot(
+        "nginx",
+        &[
+            "--port",
+            "3000",
+            "--auth",
+            "proxy",
+            "--auth-header",
+            "X-Forwarded-User",
+        ],
+        "nginx-proxy-auth.conf",
+    );
+}
+
+#[test]
+fn test_render_dnsmasq() {
+    check_snapshot("dnsmasq", &[], "dnsmasq.conf");
+}
+
+#[test]
+fn test_render_systemd() {
+    check_snapshot(
+        "systemd",
+        &[
+            "--port",
+            "8080",
+            "--auth",
+            "proxy",
+            "--admin-user",
+            "root",
+            "--dns-listen",
+            "0.0.0.0:53",
+            "--dns-upstream",
+            "1.1.1.1:53",
+        ],
+        "to-links.service",
+    );
+}
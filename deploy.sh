@@ -13,6 +13,7 @@ else
 fi
 
 BINARY_NAME="app"
+REMOTE_BIN="$TARGET_DIR/to-links-app"
 
 echo "Starting deployment to $RPI_HOST..."
 
@@ -24,21 +25,7 @@ else
   cargo build --release --target $TARGET_ARCH --manifest-path app/Cargo.toml
 fi
 
-# 2. Prepare Config Files
-echo "Generating configuration files from templates..."
-# Handle optional DB_PATH
-if [ -n "$DB_PATH" ]; then
-  export DB_ARG="--db $DB_PATH"
-else
-  export DB_ARG=""
-fi
-
-# Note: envsubst is part of gettext package
-envsubst < systemd/to-links.service.template > systemd/to-links.service
-envsubst < nginx/to-links.conf.template > nginx/to-links.conf
-envsubst < dnsmasq/shortcuts.conf.template > dnsmasq/shortcuts.conf
-
-# 3. Deploy
+# 2. Deploy
 echo "Deploying to $RPI_HOST..."
 
 # Stop service
@@ -49,20 +36,30 @@ ssh "$RPI_USER@$RPI_HOST" "mkdir -p $TARGET_DIR"
 
 # Copy binary
 echo "Copying binary..."
-scp "app/target/$TARGET_ARCH/release/$BINARY_NAME" "$RPI_USER@$RPI_HOST:$TARGET_DIR/to-links-app"
+scp "app/target/$TARGET_ARCH/release/$BINARY_NAME" "$RPI_USER@$RPI_HOST:$REMOTE_BIN"
 
-# Copy systemd service
+# 3. Generate configs with the deployed binary, so they match its settings and database
+echo "Generating configuration files..."
+# Handle optional DB_PATH
+if [ -n "$DB_PATH" ]; then
+  DB_ARG="--db $DB_PATH"
+else
+  DB_ARG=""
+fi
+SERVE_ARGS="--port $APP_PORT $DB_ARG $APP_ARGS"
+RENDER="$REMOTE_BIN $SERVE_ARGS render-config"
+
+ssh "$RPI_USER@$RPI_HOST" "$RENDER systemd --user $RPI_USER --exec $REMOTE_BIN > /tmp/to-links.service"
+ssh "$RPI_USER@$RPI_HOST" "$RENDER nginx --app-host $APP_HOST > $TARGET_DIR/to-links.conf"
+ssh "$RPI_USER@$RPI_HOST" "$RENDER dnsmasq --address $RPI_IP > $TARGET_DIR/shortcuts.conf"
+
+# Install systemd service
 echo "Installing systemd service..."
-scp "systemd/to-links.service" "$RPI_USER@$RPI_HOST:/tmp/to-links.service"
 ssh -t "$RPI_USER@$RPI_HOST" "sudo mv /tmp/to-links.service /etc/systemd/system/to-links.service && sudo systemctl daemon-reload"
 
-# Copy generated configs to the target dir for convenience
-scp "nginx/to-links.conf" "$RPI_USER@$RPI_HOST:$TARGET_DIR/"
-scp "dnsmasq/shortcuts.conf" "$RPI_USER@$RPI_HOST:$TARGET_DIR/"
-
 # 4. Start Service
 echo "Starting service..."
 ssh -t "$RPI_USER@$RPI_HOST" "sudo systemctl enable to-links && sudo systemctl start to-links"
 
 echo "Deployment successful!"
-echo "NOTE: Generated Nginx and Dnsmasq configs are at $TARGET_DIR on the remote host."
\ No newline at end of file
+echo "NOTE: Generated Nginx and Dnsmasq configs are at $TARGET_DIR on the remote host."